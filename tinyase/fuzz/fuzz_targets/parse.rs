@@ -0,0 +1,57 @@
+#![no_main]
+
+//! Walks every frame, chunk and cel of arbitrary input. Any panic is a bug.
+//!
+//! Run with `cargo +nightly fuzz run parse -- -seed_inputs=tests/anim_idle.ase`.
+
+use libfuzzer_sys::fuzz_target;
+use tinyase::parser::{HeaderReader, Rgba, chunk::{ASEChunk, CelData}};
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(reader) = HeaderReader::try_new(data) else { return };
+    let _ = reader.header();
+    let _ = reader.palette_for_frame(u16::MAX, &mut [Rgba::TRANSPARENT; 256]);
+    for frame in reader.frames() {
+        let Ok(frame) = frame else { continue };
+        for chunk in frame.chunks() {
+            match chunk {
+                Ok(ASEChunk::Cel(cel)) => {
+                    match cel.get() {
+                        Ok(CelData::Raw(raw)) => for _pixel in &raw {},
+                        Ok(CelData::Compressed(image)) => {
+                            let mut buf = [0u8; 4096];
+                            if let Ok(raw) = image.decompress(reader.color_depth(), &mut buf) {
+                                for _pixel in &raw {}
+                            }
+                        }
+                        Ok(CelData::Tilemap(tilemap)) => {
+                            let mut buf = [0u8; 4096];
+                            if let Ok(tiles) = tilemap.decompress(&mut buf) {
+                                for _tile in tiles.tiles() {}
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+                Ok(ASEChunk::Palette(palette)) => for _entry in palette.entries() {},
+                Ok(ASEChunk::OldPalette(palette)) => for _entry in palette.entries() {},
+                Ok(ASEChunk::Tags(tags)) => for _tag in &tags {},
+                Ok(ASEChunk::ExternalFiles(files)) => for _file in &files {},
+                Ok(ASEChunk::Slice(slice)) => for _key in slice.keys() {},
+                Ok(ASEChunk::Mask(mask)) => for _pixel in mask.pixels() {},
+                Ok(ASEChunk::Tileset(tileset)) => {
+                    let mut buf = [0u8; 4096];
+                    if let Ok(tiles) = tileset.decompress(reader.color_depth(), &mut buf) {
+                        for tile in tiles.tiles() {
+                            let _ = (tile.pixel(0, 0), tile.user_data());
+                        }
+                    }
+                }
+                Ok(ASEChunk::UserData(user_data)) => for (_key, map) in user_data.properties() {
+                    for _property in &map {}
+                },
+                _ => {}
+            }
+        }
+    }
+});
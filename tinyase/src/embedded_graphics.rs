@@ -1,6 +1,6 @@
 use core::convert::Infallible;
 
-use embedded_graphics::{Pixel, framebuffer::{Framebuffer, buffer_size, buffer_size_bpp}, pixelcolor::{BinaryColor, PixelColor, raw::{BigEndian, RawU1, RawU2}}, prelude::Point, primitives::Rectangle};
+use embedded_graphics::{Pixel, framebuffer::{Framebuffer, buffer_size_bpp}, pixelcolor::{BinaryColor, raw::{BigEndian, RawU1, RawU2}}, prelude::Point, primitives::Rectangle};
 use embedded_graphics::prelude::*;
 
 use crate::parser::chunk::embedded_graphics_impl::AlphaBinaryColor;
@@ -0,0 +1,157 @@
+use core::fmt::{self, Display, Formatter};
+
+use thiserror::Error as ThisError;
+
+/// Error returned by every fallible parsing function in the crate.
+///
+/// Besides the [`ErrorKind`], it records where in the file the problem was found
+/// so that a broken asset can be tracked down to the frame and chunk at fault.
+#[derive(ThisError, Debug, Clone, Copy, PartialEq, Eq)]
+#[error("{kind} at byte offset {offset:#x}{}", Location { frame: *frame, chunk: *chunk, chunk_type: *chunk_type })]
+pub struct Error {
+    pub kind: ErrorKind,
+    /// Byte offset from the start of the file.
+    ///
+    /// Functions decoding a single chunk payload, frame or compressed image take only
+    /// that part of the file, and their offsets are relative to the start of it. The
+    /// readers walking a whole file turn them into file offsets and add the frame and
+    /// chunk.
+    pub offset: usize,
+    /// Index of the frame being parsed, if any
+    pub frame: Option<u16>,
+    /// Index of the chunk within its frame, if any
+    pub chunk: Option<u32>,
+    /// Type of the chunk being parsed, if any
+    pub chunk_type: Option<u16>,
+}
+
+#[derive(ThisError, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    #[error("unexpected end of data, needed {needed} bytes but {available} are available")]
+    Truncated { needed: usize, available: usize },
+    #[error("chunk declares {declared} bytes but {available} are available")]
+    TruncatedChunk { declared: u32, available: usize },
+    #[error("invalid chunk size {0}")]
+    InvalidChunkSize(u32),
+    #[error("invalid frame size {0}")]
+    InvalidFrameSize(u32),
+    #[error("file declares {filesize} bytes but {available} are available")]
+    TruncatedFile { filesize: u32, available: usize },
+    #[error("invalid file size {0}")]
+    InvalidFileSize(u32),
+    #[error("header declares {frames} frames but only {frame_bytes} bytes of frame data follow")]
+    TooManyFrames { frames: u16, frame_bytes: usize },
+    #[error("bad magic number, expected {expected:#x} but found {found:#x}")]
+    BadMagic { expected: u16, found: u16 },
+    #[error("invalid {field} value {value}")]
+    BadEnumValue { field: &'static str, value: u32 },
+    #[error("invalid UTF-8 string")]
+    InvalidUtf8,
+    #[error("buffer too small, {needed} needed but {available} available")]
+    BufferTooSmall { needed: usize, available: usize },
+    #[error("invalid palette range {first}..={last}")]
+    InvalidPaletteRange { first: u32, last: u32 },
+    #[error("values nested deeper than {0} levels")]
+    NestingTooDeep(u8),
+    #[error("invalid properties size {0}")]
+    InvalidPropertiesSize(u32),
+    #[error("invalid compressed data")]
+    InvalidCompressedData,
+    #[error("compressed image doesn't decompress to the expected {expected} bytes")]
+    ImageSizeMismatch { expected: usize },
+    #[error("image is too large to fit in memory")]
+    ImageTooLarge,
+    #[error("no image data, it is stored in an external file")]
+    MissingImage,
+    #[error("external file {0} could not be resolved")]
+    MissingExternalFile(u32),
+}
+
+impl Error {
+    pub fn new(kind: ErrorKind, offset: usize) -> Self {
+        Error { kind, offset, frame: None, chunk: None, chunk_type: None }
+    }
+
+    pub fn truncated(offset: usize, needed: usize, available: usize) -> Self {
+        Self::new(ErrorKind::Truncated { needed, available }, offset)
+    }
+
+    pub fn bad_enum(offset: usize, field: &'static str, value: u32) -> Self {
+        Self::new(ErrorKind::BadEnumValue { field, value }, offset)
+    }
+
+    /// Shifts a relative offset by `base`, turning it into an offset into the enclosing buffer.
+    pub fn offset_by(mut self, base: usize) -> Self {
+        self.offset += base;
+        self
+    }
+
+    /// Records the frame index, unless a more specific one is already set.
+    pub fn in_frame(mut self, frame: u16) -> Self {
+        self.frame.get_or_insert(frame);
+        self
+    }
+
+    /// Records the chunk index and type, unless already set.
+    pub fn in_chunk(mut self, chunk: u32, chunk_type: u16) -> Self {
+        self.chunk_type.get_or_insert(chunk_type);
+        self.in_chunk_index(chunk)
+    }
+
+    /// Records the chunk index only, for chunks whose header couldn't be read.
+    pub fn in_chunk_index(mut self, chunk: u32) -> Self {
+        self.chunk.get_or_insert(chunk);
+        self
+    }
+}
+
+struct Location {
+    frame: Option<u16>,
+    chunk: Option<u32>,
+    chunk_type: Option<u16>,
+}
+
+impl Display for Location {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if let Some(frame) = self.frame {
+            write!(f, " in frame {}", frame)?;
+        }
+        if let Some(chunk) = self.chunk {
+            write!(f, ", chunk {}", chunk)?;
+        }
+        if let Some(chunk_type) = self.chunk_type {
+            write!(f, " (type {:#06x})", chunk_type)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn display_includes_location() {
+        let e = Error::truncated(4, 10, 2).offset_by(0x100).in_chunk(3, 0x2005).in_frame(7);
+        assert_eq!(
+            std::format!("{}", e),
+            "unexpected end of data, needed 10 bytes but 2 are available at byte offset 0x104 in frame 7, chunk 3 (type 0x2005)"
+        );
+    }
+
+    #[test]
+    fn display_without_chunk_type() {
+        let e = Error::truncated(0, 6, 3).in_chunk_index(2).in_frame(0);
+        assert_eq!(e.chunk_type, None);
+        assert_eq!(
+            std::format!("{}", e),
+            "unexpected end of data, needed 6 bytes but 3 are available at byte offset 0x0 in frame 0, chunk 2"
+        );
+    }
+
+    #[test]
+    fn inner_context_wins() {
+        let e = Error::new(ErrorKind::InvalidUtf8, 0).in_frame(1).in_frame(2);
+        assert_eq!(e.frame, Some(1));
+    }
+}
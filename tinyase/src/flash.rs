@@ -0,0 +1,243 @@
+//! Reading files stored in raw NOR flash through [`embedded_storage`].
+//!
+//! [`FlashCursor`] turns a [`ReadNorFlash`] into an `embedded_io` `Read + Seek` source so
+//! the [`StreamReader`] can parse a file placed at any offset of an asset partition.
+
+use embedded_io::{ErrorType, Read, Seek, SeekFrom};
+use embedded_storage::nor_flash::{NorFlashError, NorFlashErrorKind, ReadNorFlash};
+
+use crate::stream::StreamReader;
+
+/// A [`StreamReader`] over a file in NOR flash, caching `CACHE` bytes at a time.
+pub type FlashReader<F, const CACHE: usize> = StreamReader<FlashCursor<F, CACHE>>;
+
+#[derive(Debug)]
+pub enum FlashError<E> {
+    /// Error from the underlying flash
+    Flash(E),
+    /// Seek to a position before the start of the file, or seek or read beyond the
+    /// 32 bit flash address space
+    InvalidSeek,
+}
+
+impl<E: NorFlashError> embedded_io::Error for FlashError<E> {
+    fn kind(&self) -> embedded_io::ErrorKind {
+        match self {
+            FlashError::Flash(e) => match e.kind() {
+                NorFlashErrorKind::NotAligned | NorFlashErrorKind::OutOfBounds => embedded_io::ErrorKind::InvalidInput,
+                _ => embedded_io::ErrorKind::Other,
+            },
+            FlashError::InvalidSeek => embedded_io::ErrorKind::InvalidInput,
+        }
+    }
+}
+
+/// `Read + Seek` view of the flash contents starting at `base`.
+///
+/// Reads from the flash are always `CACHE` bytes long and aligned to `CACHE`, which must be
+/// a non-zero multiple of the flash `READ_SIZE`. The last block read is kept, so the small
+/// header reads done by the parser rarely touch the flash twice.
+pub struct FlashCursor<F, const CACHE: usize> {
+    flash: F,
+    base: u32,
+    pos: u64,
+    cache: [u8; CACHE],
+    /// Flash address and valid length of the cached block
+    cached: Option<(u32, usize)>,
+}
+
+impl<F: ReadNorFlash, const CACHE: usize> FlashCursor<F, CACHE> {
+    const VALID_CACHE: () = assert!(CACHE > 0 && CACHE.is_multiple_of(F::READ_SIZE), "CACHE must be a multiple of READ_SIZE");
+
+    /// Creates a cursor over the file stored at flash address `base`.
+    pub fn new(flash: F, base: u32) -> Self {
+        let () = Self::VALID_CACHE;
+        FlashCursor { flash, base, pos: 0, cache: [0; CACHE], cached: None }
+    }
+
+    /// Opens the file stored at flash address `base`, see [`StreamReader::new`].
+    pub fn open(flash: F, base: u32) -> Result<FlashReader<F, CACHE>, crate::stream::StreamError<FlashError<F::Error>>> {
+        StreamReader::new(Self::new(flash, base))
+    }
+
+    pub fn into_inner(self) -> F {
+        self.flash
+    }
+
+    /// Bytes from `base` to the end of the flash
+    fn len(&self) -> u64 {
+        (self.flash.capacity() as u64).saturating_sub(self.base as u64)
+    }
+
+    /// Makes sure the block holding flash address `addr` is cached and returns its
+    /// start address and contents.
+    fn block(&mut self, addr: u32) -> Result<(u32, &[u8]), FlashError<F::Error>> {
+        let start = addr - addr % CACHE as u32;
+        let (cached_start, cached_len) = match self.cached {
+            Some((cached_start, len)) if cached_start == start => (cached_start, len),
+            _ => {
+                self.cached = None;
+                let len = (self.flash.capacity() - start as usize).min(CACHE);
+                self.flash.read(start, &mut self.cache[..len]).map_err(FlashError::Flash)?;
+                self.cached = Some((start, len));
+                (start, len)
+            }
+        };
+        Ok((cached_start, &self.cache[..cached_len]))
+    }
+}
+
+impl<F: ReadNorFlash, const CACHE: usize> ErrorType for FlashCursor<F, CACHE> {
+    type Error = FlashError<F::Error>;
+}
+
+impl<F: ReadNorFlash, const CACHE: usize> Read for FlashCursor<F, CACHE> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        if buf.is_empty() || self.pos >= self.len() {
+            return Ok(0);
+        }
+        let addr = u32::try_from(self.pos).ok()
+            .and_then(|pos| self.base.checked_add(pos))
+            .ok_or(FlashError::InvalidSeek)?;
+        let (start, block) = self.block(addr)?;
+        let available = &block[(addr - start) as usize..];
+        let n = available.len().min(buf.len());
+        buf[..n].copy_from_slice(&available[..n]);
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl<F: ReadNorFlash, const CACHE: usize> Seek for FlashCursor<F, CACHE> {
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64, Self::Error> {
+        let pos = match pos {
+            SeekFrom::Start(p) => Some(p),
+            SeekFrom::End(d) => self.len().checked_add_signed(d),
+            SeekFrom::Current(d) => self.pos.checked_add_signed(d),
+        };
+        // Positions past the end are allowed and read as end of file
+        self.pos = pos.filter(|p| *p <= u32::MAX as u64).ok_or(FlashError::InvalidSeek)?;
+        Ok(self.pos)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parser::{HeaderReader, chunk::ASEChunk};
+    use std::{vec, vec::Vec};
+
+    struct RamFlash {
+        data: Vec<u8>,
+        reads: usize,
+    }
+
+    impl embedded_storage::nor_flash::ErrorType for RamFlash {
+        type Error = NorFlashErrorKind;
+    }
+
+    impl ReadNorFlash for RamFlash {
+        const READ_SIZE: usize = 4;
+
+        fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+            embedded_storage::nor_flash::check_read(self, offset, bytes.len())?;
+            self.reads += 1;
+            bytes.copy_from_slice(&self.data[offset as usize..offset as usize + bytes.len()]);
+            Ok(())
+        }
+
+        fn capacity(&self) -> usize {
+            self.data.len()
+        }
+    }
+
+    #[test]
+    fn flash_matches_slice_reader() {
+        let v = std::fs::read("tests/anim_idle.ase").unwrap();
+        let slice_reader = HeaderReader::new(&v);
+
+        // place the file at an unaligned address in the middle of the flash
+        let base = 0x1003;
+        let mut data = vec![0xFF; 0x4000];
+        data[base..base + v.len()].copy_from_slice(&v);
+        let flash = RamFlash { data, reads: 0 };
+
+        let mut reader = FlashCursor::<_, 64>::open(flash, base as u32).unwrap();
+        assert_eq!(reader.header().frames(), 12);
+
+        let mut scratch = [0u8; 2048];
+        let mut frames = reader.frames();
+        let mut slice_frames = slice_reader.frames();
+        while let Some(frame) = frames.next(&mut reader).unwrap() {
+            let slice_frame = slice_frames.next().unwrap().unwrap();
+            let mut chunks = reader.chunks(&frame);
+            let mut slice_chunks = slice_frame.chunks();
+            while let Some(chunk) = chunks.next(&mut reader).unwrap() {
+                let expected = slice_chunks.next().unwrap().unwrap();
+                match (reader.read_chunk(&chunk, &mut scratch).unwrap(), expected) {
+                    (ASEChunk::Layer(a), ASEChunk::Layer(b)) => assert_eq!(a.name, b.name),
+                    (ASEChunk::Cel(a), ASEChunk::Cel(b)) => assert_eq!(a.cel_header.layer_index(), b.cel_header.layer_index()),
+                    (ASEChunk::Unknown(a, da), ASEChunk::Unknown(b, db)) => assert_eq!((a, da), (b, db)),
+                    // only chunks read from a whole frame carry user data
+                    (ASEChunk::Tags(a), ASEChunk::Tags(b)) => {
+                        assert!(a.iter().map(|t| (t.from, t.to, t.name)).eq(b.iter().map(|t| (t.from, t.to, t.name))))
+                    }
+                    (a, b) => assert_eq!(std::format!("{a:?}"), std::format!("{b:?}")),
+                }
+            }
+        }
+
+        // every block of the file is read about once
+        let flash = reader.into_inner().into_inner();
+        assert!(flash.reads < 2 * v.len().div_ceil(64), "{} reads", flash.reads);
+    }
+
+    #[test]
+    fn flash_reads_at_end_of_capacity() {
+        let v = std::fs::read("tests/anim_idle.ase").unwrap();
+        // the file ends in a partial cache block at the very end of the flash
+        let mut data = vec![0xFF; 8];
+        data.extend_from_slice(&v);
+        data.resize(data.len().next_multiple_of(4), 0);
+        let mut reader = FlashCursor::<_, 256>::open(RamFlash { data, reads: 0 }, 8).unwrap();
+        let mut frames = reader.frames();
+        let mut count = 0;
+        while let Some(frame) = frames.next(&mut reader).unwrap() {
+            let mut chunks = reader.chunks(&frame);
+            while chunks.next(&mut reader).unwrap().is_some() {}
+            count += 1;
+        }
+        assert_eq!(count, 12);
+    }
+
+    /// A flash larger than the 32 bit address space, which can't be read past it
+    struct HugeFlash;
+
+    impl embedded_storage::nor_flash::ErrorType for HugeFlash {
+        type Error = NorFlashErrorKind;
+    }
+
+    impl ReadNorFlash for HugeFlash {
+        const READ_SIZE: usize = 4;
+
+        fn read(&mut self, _offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+            bytes.fill(0);
+            Ok(())
+        }
+
+        fn capacity(&self) -> usize {
+            usize::MAX
+        }
+    }
+
+    #[test]
+    #[cfg(target_pointer_width = "64")]
+    fn flash_address_overflow() {
+        let mut cursor = FlashCursor::<_, 4>::new(HugeFlash, 16);
+        // the last addressable byte can be read, the one after it can't
+        cursor.seek(SeekFrom::Start(u32::MAX as u64 - 16)).unwrap();
+        assert_eq!(cursor.read(&mut [0; 4]).unwrap(), 1);
+        cursor.seek(SeekFrom::Start(u32::MAX as u64 - 15)).unwrap();
+        assert!(matches!(cursor.read(&mut [0; 4]), Err(FlashError::InvalidSeek)));
+    }
+}
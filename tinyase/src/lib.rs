@@ -19,23 +19,38 @@
     clippy::print_stdout,
     clippy::todo,
     //clippy::unwrap_used, // not yet in stable
-    clippy::wrong_pub_self_convention
+    clippy::wrong_self_convention
 )]
+#![cfg_attr(test, allow(clippy::panic, clippy::print_stdout))]
 
+#[cfg(feature = "alloc")]
+extern crate alloc;
+
+pub mod error;
 pub mod parser;
+pub use error::{Error, ErrorKind};
 pub use parser::ASEHeader;
+pub mod visit;
+pub use visit::{AseVisitor, walk};
 
 #[cfg(feature = "embedded_graphics")]
 pub mod embedded_graphics;
 
+#[cfg(feature = "embedded_io")]
+pub mod stream;
+
+#[cfg(feature = "embedded_storage")]
+pub mod flash;
+
+#[cfg(test)]
+pub(crate) mod test_util;
+
 pub fn add(left: u64, right: u64) -> u64 {
     left + right
 }
 
 #[cfg(test)]
 mod tests {
-    use super::*;
-
 //     #[test]
 //     fn test_decompress() {
 //         use compression::prelude::*;
@@ -1,77 +1,533 @@
-use thiserror::Error;
+use core::cell::Cell;
+
+use bitflags::bitflags;
 use zerocopy::*;
-use core::iter::Iterator;
 
+use crate::error::{Error, ErrorKind};
+use crate::parser::chunk::{ASEChunk, RawChunk};
+use crate::parser::chunk::color_profile::ColorProfile;
+use crate::parser::chunk::external_files::{ExternalFile, ExternalFileKind, ExternalFiles, ExternalResolver};
+use crate::parser::chunk::slice::Slice;
+use crate::parser::chunk::tags::{Tag, Tags};
+use crate::parser::chunk::tileset::Tileset;
+use crate::parser::chunk::user_data::UserData;
 use crate::parser::frame::FrameListIterator;
 
-use self::chunk::*;
-
+mod bytes;
+mod inflate;
 pub mod chunk;
 pub mod frame;
+pub mod types;
+
+pub use chunk::palette::Rgba;
+pub use types::{Fixed, Point, Rect, Size};
 
+/// Size of the fixed ASE header in bytes.
+pub const HEADER_SIZE: usize = 128;
+/// Size of a frame header in bytes.
+pub const FRAME_HEADER_SIZE: usize = 16;
+
+const HEADER_MAGIC: u16 = 0xA5E0;
 
 pub struct HeaderReader<'a> {
-    data: &'a[u8]
+    header: &'a ASEHeader,
+    depth: ColorDepth,
+    rest: &'a [u8],
+    /// Whether any frame has a new palette chunk, found on the first palette lookup
+    has_new_palette: Cell<Option<bool>>,
 }
 
 impl<'a> HeaderReader<'a> {
+    /// Creates a reader over a complete aseprite file.
+    ///
+    /// # Panics
+    /// Panics if `data` is not a valid aseprite file, use [`HeaderReader::try_new`]
+    /// when the input is not trusted.
     pub fn new(data: &'a[u8]) -> Self {
-        HeaderReader { data }
+        Self::try_new(data).expect("invalid aseprite file")
+    }
+
+    /// Creates a reader over a complete aseprite file, validating the header up front.
+    ///
+    /// Checks the magic number, that the buffer holds at least `filesize` bytes,
+    /// that the color depth is 8, 16 or 32 and that the declared frame count fits
+    /// in the file.
+    pub fn try_new(data: &'a[u8]) -> Result<Self, Error> {
+        let (header, _) = parse_header(data)?;
+        header.validate(data.len())?;
+
+        let filesize = header.filesize() as usize;
+        let depth = header.color_depth()?;
+        Ok(HeaderReader { header, depth, rest: &data[HEADER_SIZE..filesize], has_new_palette: Cell::new(None) })
     }
 
     pub fn header(&self) -> &'a ASEHeader {
-        let (header, next) = parse_header(self.data).unwrap();
-        header
+        self.header
+    }
+
+    /// Color depth of the sprite, already validated by [`HeaderReader::try_new`].
+    pub fn color_depth(&self) -> ColorDepth {
+        self.depth
     }
     
     pub fn frames(&self) -> FrameListIterator<'a> {
-        let (header, next) = parse_header(self.data).unwrap();
         FrameListIterator {
-            rest: next,
-            remaining: header.frames,
+            rest: self.rest,
+            remaining: self.header.frames(),
+            offset: HEADER_SIZE,
+            index: 0,
+            speed: self.header.speed(),
+            flags: self.header.flags(),
+        }
+    }
+
+    /// The chunks of the first frame, where Aseprite writes the chunks that apply to the
+    /// whole sprite. Payloads are left to the caller to decode.
+    fn first_frame_chunks(&self) -> impl Iterator<Item = Result<RawChunk<'a>, Error>> + 'a {
+        let (mut chunks, error) = match self.frames().next() {
+            Some(Ok(frame)) => (Some(frame.chunks()), None),
+            Some(Err(e)) => (None, Some(e)),
+            None => (None, None),
+        };
+        error.map(Err).into_iter().chain(core::iter::from_fn(move || chunks.as_mut()?.next_raw()))
+    }
+
+    /// Decodes the chunks of type `chunk_type` in the first frame, returning the first
+    /// one `select` accepts.
+    fn find_in_first_frame<T>(&self, chunk_type: u16, select: impl FnMut(ASEChunk<'a>) -> Option<T>) -> Result<Option<T>, Error> {
+        Ok(self.locate_in_first_frame(chunk_type, select)?.map(|(_, found)| found))
+    }
+
+    /// Like [`HeaderReader::find_in_first_frame`], also returning the chunk that was found
+    /// so that errors about it can be located.
+    fn locate_in_first_frame<T>(&self, chunk_type: u16, mut select: impl FnMut(ASEChunk<'a>) -> Option<T>) -> Result<Option<(RawChunk<'a>, T)>, Error> {
+        for chunk in self.first_frame_chunks() {
+            let chunk = chunk?;
+            if chunk.chunk_type() == chunk_type
+                && let Some(found) = select(chunk.decode()?)
+            {
+                return Ok(Some((chunk, found)));
+            }
         }
+        Ok(None)
+    }
+
+    /// The tags chunk of the file, which Aseprite writes in the first frame.
+    pub fn tags(&self) -> Result<Option<Tags<'a>>, Error> {
+        self.find_in_first_frame(0x2018, |chunk| match chunk {
+            ASEChunk::Tags(tags) => Some(tags),
+            _ => None,
+        })
+    }
+
+    /// The color profile of the sprite, which Aseprite writes in the first frame.
+    pub fn color_profile(&self) -> Result<Option<ColorProfile<'a>>, Error> {
+        self.find_in_first_frame(0x2007, |chunk| match chunk {
+            ASEChunk::ColorProfile(profile) => Some(profile),
+            _ => None,
+        })
+    }
+
+    /// The external files chunk of the file, which Aseprite writes in the first frame.
+    pub fn external_files(&self) -> Result<Option<ExternalFiles<'a>>, Error> {
+        Ok(self.locate_external_files()?.map(|(_, files)| files))
+    }
+
+    fn locate_external_files(&self) -> Result<Option<(RawChunk<'a>, ExternalFiles<'a>)>, Error> {
+        self.locate_in_first_frame(0x2008, |chunk| match chunk {
+            ASEChunk::ExternalFiles(files) => Some(files),
+            _ => None,
+        })
+    }
+
+    /// Finds a tileset of this file by its ID. Aseprite writes every tileset in the
+    /// first frame.
+    pub fn tileset(&self, id: u32) -> Result<Option<Tileset<'a>>, Error> {
+        Ok(self.locate_tileset(id)?.map(|(_, tileset)| tileset))
+    }
+
+    fn locate_tileset(&self, id: u32) -> Result<Option<(RawChunk<'a>, Tileset<'a>)>, Error> {
+        self.locate_in_first_frame(0x2023, |chunk| match chunk {
+            ASEChunk::Tileset(tileset) if tileset.header.id() == id => Some(tileset),
+            _ => None,
+        })
+    }
+
+    /// Finds a tileset by its ID like [`HeaderReader::tileset`], but when the tiles live
+    /// in another file, returns the tileset of that file as handed out by `resolver`.
+    ///
+    /// A tileset that has a copy of its tiles embedded is returned as is. A file that
+    /// can't be resolved is reported as [`ErrorKind::MissingExternalFile`], located at the
+    /// tileset chunk. Errors found in the other file carry offsets into that file.
+    pub fn resolve_tileset(&self, id: u32, resolver: &mut impl ExternalResolver<'a>) -> Result<Option<Tileset<'a>>, Error> {
+        let Some((chunk, tileset)) = self.locate_tileset(id)? else { return Ok(None) };
+        let Some(external) = tileset.external().filter(|_| tileset.compressed_image().is_none()) else {
+            return Ok(Some(tileset));
+        };
+        let file = self.external_files()?.and_then(|files| files.get(external.file_id));
+        let other = resolve(file, external.file_id, &chunk, resolver)?;
+        other.tileset(external.tileset_id)
+    }
+
+    /// Resolves the palette of the external palette file the sprite refers to, if any,
+    /// into `palette` and returns its size. See [`HeaderReader::palette_for_frame`].
+    ///
+    /// Aseprite links at most one palette file. When the external files chunk lists
+    /// several, the first one is used. A file that can't be resolved is reported as
+    /// [`ErrorKind::MissingExternalFile`], located at the external files chunk.
+    pub fn external_palette(&self, resolver: &mut impl ExternalResolver<'a>, palette: &mut [Rgba]) -> Result<Option<usize>, Error> {
+        let Some((chunk, files)) = self.locate_external_files()? else { return Ok(None) };
+        let Some(file) = files.iter().find(|f| f.kind == ExternalFileKind::ExternalPalette) else { return Ok(None) };
+        let other = resolve(Some(file), file.id, &chunk, resolver)?;
+        other.palette_for_frame(0, palette).map(Some)
+    }
+
+    /// Finds an animation tag by its exact, case sensitive name.
+    pub fn tag(&self, name: &str) -> Result<Option<Tag<'a>>, Error> {
+        Ok(self.tags()?.and_then(|tags| tags.get(name)))
+    }
+
+    /// Finds a slice by its exact, case sensitive name. Aseprite writes every slice
+    /// in the first frame.
+    pub fn slice(&self, name: &str) -> Result<Option<Slice<'a>>, Error> {
+        self.find_in_first_frame(0x2022, |chunk| match chunk {
+            ASEChunk::Slice(slice) if slice.name == name => Some(slice),
+            _ => None,
+        })
+    }
+
+    /// User data of the sprite, the user data chunk that follows the palette chunks
+    /// of the first frame.
+    pub fn user_data(&self) -> Result<Option<UserData<'a>>, Error> {
+        let mut previous = 0;
+        for chunk in self.first_frame_chunks() {
+            let chunk = chunk?;
+            if chunk.chunk_type() == 0x2020
+                && matches!(previous, 0x0004 | 0x0011 | 0x2019)
+                && let ASEChunk::UserData(user_data) = chunk.decode()?
+            {
+                return Ok(Some(user_data));
+            }
+            previous = chunk.chunk_type();
+        }
+        Ok(None)
+    }
+
+    /// Resolves the palette in effect at `frame` into `palette` and returns its size.
+    ///
+    /// Palette chunks can appear in any frame and may change only part of the palette,
+    /// so the chunks of frames `0..=frame` are applied in order. Old palette chunks
+    /// (0x0004 and 0x0011) are only used when no frame of the file has a new one (0x2019),
+    /// so every frame resolves against the same kind of palette.
+    /// Entries that are never set are left [`Rgba::TRANSPARENT`]. Fails with
+    /// [`ErrorKind::BufferTooSmall`] when the palette has more entries than `palette`
+    /// can hold.
+    pub fn palette_for_frame(&self, frame: u16, palette: &mut [Rgba]) -> Result<usize, Error> {
+        let use_old = !self.has_new_palette()?;
+
+        palette.fill(Rgba::TRANSPARENT);
+        let available = palette.len();
+        let mut size = 0;
+        // the new palette chunk that set `size`
+        let mut sized_by = None;
+        for f in self.frames().take(frame as usize + 1) {
+            let mut chunks = f?.chunks();
+            while let Some(chunk) = chunks.next_raw() {
+                let chunk = chunk?;
+                let mut set = |index: u32, color: Rgba| {
+                    let too_small = ErrorKind::BufferTooSmall { needed: index as usize + 1, available };
+                    *palette.get_mut(index as usize).ok_or_else(|| chunk.locate(Error::new(too_small, 0)))? = color;
+                    Ok::<_, Error>(())
+                };
+                match chunk.chunk_type() {
+                    0x2019 => {
+                        let ASEChunk::Palette(p) = chunk.decode()? else { continue };
+                        size = p.header.size() as usize;
+                        sized_by = Some(chunk);
+                        for (index, color, _) in p.entries() {
+                            set(index, color)?;
+                        }
+                    }
+                    0x0004 | 0x0011 if use_old => {
+                        let ASEChunk::OldPalette(p) = chunk.decode()? else { continue };
+                        for (index, color) in p.entries() {
+                            set(index, color)?;
+                            size = size.max(index as usize + 1);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+        if let Some(chunk) = sized_by
+            && size > available
+        {
+            return Err(chunk.locate(Error::new(ErrorKind::BufferTooSmall { needed: size, available }, 0)));
+        }
+        Ok(size)
+    }
+
+    fn has_new_palette(&self) -> Result<bool, Error> {
+        if let Some(has) = self.has_new_palette.get() {
+            return Ok(has);
+        }
+        let mut has = false;
+        'frames: for f in self.frames() {
+            let mut chunks = f?.chunks();
+            while let Some(chunk) = chunks.next_raw() {
+                if chunk?.chunk_type() == 0x2019 {
+                    has = true;
+                    break 'frames;
+                }
+            }
+        }
+        self.has_new_palette.set(Some(has));
+        Ok(has)
     }
 }
 
 
+/// Opens the external file `file_id`, whose entry is `file`, reporting a missing entry or
+/// file at the chunk referring to it.
+fn resolve<'a>(file: Option<ExternalFile<'a>>, file_id: u32, referrer: &RawChunk<'a>, resolver: &mut impl ExternalResolver<'a>) -> Result<HeaderReader<'a>, Error> {
+    let data = file.and_then(|file| resolver.resolve(&file))
+        .ok_or_else(|| referrer.locate(Error::new(ErrorKind::MissingExternalFile(file_id), 0)))?;
+    HeaderReader::try_new(data)
+}
 
-#[derive(Debug, FromBytes, KnownLayout, Immutable)]
-#[repr(packed)]
+/// The 128 byte file header. All multi-byte fields are stored little-endian.
+#[derive(Debug, FromBytes, KnownLayout, Immutable, Unaligned)]
+#[repr(C)]
 pub struct ASEHeader {
-    pub filesize: u32,
-    _magic: u16,
-    pub frames: u16,
-    pub width: u16,
-    pub height: u16,
-    pub depth: u16,
-    pub flags: u32,
-    _deprecated_speed: u16,
-    _reserved_11: u32,
-    _reserved_12: u32,
-    pub pallet_transparent_idx: u8,
+    filesize: U32<LE>,
+    magic: U16<LE>,
+    frames: U16<LE>,
+    width: U16<LE>,
+    height: U16<LE>,
+    depth: U16<LE>,
+    flags: U32<LE>,
+    speed: U16<LE>,
+    _reserved_11: U32<LE>,
+    _reserved_12: U32<LE>,
+    transparent_index: u8,
     _reserved2: [u8; 3],
-    pub num_colors: u16,
-    pub pixel_width: u8,
-    pub pixel_height: u8,
-    pub xgrid: u16,
-    pub ygrid: u16,
-    pub grid_width: u16,
-    pub grid_height: u16,
+    num_colors: U16<LE>,
+    pixel_width: u8,
+    pixel_height: u8,
+    grid_x: I16<LE>,
+    grid_y: I16<LE>,
+    grid_width: U16<LE>,
+    grid_height: U16<LE>,
     _reserved3: [u8; 84],
 }
 
+impl ASEHeader {
+    /// Size of the whole file in bytes
+    pub fn filesize(&self) -> u32 {
+        self.filesize.get()
+    }
+
+    pub fn frames(&self) -> u16 {
+        self.frames.get()
+    }
+
+    pub fn width(&self) -> u16 {
+        self.width.get()
+    }
+
+    pub fn height(&self) -> u16 {
+        self.height.get()
+    }
+
+    /// Color depth in bits per pixel, see [`ASEHeader::color_depth`] for the typed value
+    pub fn depth(&self) -> u16 {
+        self.depth.get()
+    }
+
+    pub fn color_depth(&self) -> Result<ColorDepth, Error> {
+        ColorDepth::try_from(self.depth()).map_err(|e| e.offset_by(12))
+    }
+
+    /// Header flags, unknown bits are dropped
+    pub fn flags(&self) -> HeaderFlags {
+        HeaderFlags::from_bits_truncate(self.flags.get())
+    }
+
+    /// Deprecated frame speed in milliseconds, superseded by the per-frame duration
+    pub fn speed(&self) -> u16 {
+        self.speed.get()
+    }
+
+    /// Palette index of the transparent color in non-background layers (indexed sprites only)
+    pub fn transparent_index(&self) -> u8 {
+        self.transparent_index
+    }
+
+    /// Number of colors, 0 means 256 for old sprites
+    pub fn num_colors(&self) -> u16 {
+        self.num_colors.get()
+    }
+
+    pub fn pixel_width(&self) -> u8 {
+        self.pixel_width
+    }
+
+    pub fn pixel_height(&self) -> u8 {
+        self.pixel_height
+    }
+
+    /// Pixel aspect ratio, 1:1 when either field is zero
+    pub fn pixel_ratio(&self) -> PixelRatio {
+        PixelRatio::new(self.pixel_width, self.pixel_height)
+    }
+
+    pub fn grid_x(&self) -> i16 {
+        self.grid_x.get()
+    }
+
+    pub fn grid_y(&self) -> i16 {
+        self.grid_y.get()
+    }
 
+    pub fn grid_width(&self) -> u16 {
+        self.grid_width.get()
+    }
 
-#[derive(Error, Debug)]
-pub enum HeaderParseError {
-    #[error("Cast error")]
-    CastError,
+    pub fn grid_height(&self) -> u16 {
+        self.grid_height.get()
+    }
+
+    /// The editor grid, `None` when the grid width or height is zero
+    pub fn grid(&self) -> Option<Grid> {
+        let (width, height) = (self.grid_width(), self.grid_height());
+        if width == 0 || height == 0 {
+            return None;
+        }
+        Some(Grid { x: self.grid_x(), y: self.grid_y(), width, height })
+    }
+
+    /// Checks the header against the spec and against `available` bytes of input.
+    pub fn validate(&self, available: usize) -> Result<(), Error> {
+        let magic = self.magic.get();
+        if magic != HEADER_MAGIC {
+            return Err(Error::new(ErrorKind::BadMagic { expected: HEADER_MAGIC, found: magic }, 4));
+        }
+
+        let filesize = self.filesize();
+        if (filesize as usize) < HEADER_SIZE {
+            return Err(Error::new(ErrorKind::InvalidFileSize(filesize), 0));
+        }
+        if filesize as usize > available {
+            return Err(Error::new(ErrorKind::TruncatedFile { filesize, available }, 0));
+        }
+
+        self.color_depth()?;
+
+        let frames = self.frames();
+        let frame_bytes = filesize as usize - HEADER_SIZE;
+        if frames as usize * FRAME_HEADER_SIZE > frame_bytes {
+            return Err(Error::new(ErrorKind::TooManyFrames { frames, frame_bytes }, 6));
+        }
+
+        Ok(())
+    }
+}
+
+bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct HeaderFlags : u32 {
+        /// Layer opacity has a valid value
+        const LAYER_OPACITY_VALID = 0x1;
+        /// Layer blend mode and opacity are valid for groups, which should be
+        /// composited separately first when rendering
+        const GROUP_BLEND_VALID = 0x2;
+        /// Layers have a UUID
+        const LAYERS_HAVE_UUID = 0x4;
+    }
+}
+
+#[repr(u16)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorDepth {
+    Indexed = 8,
+    Grayscale = 16,
+    Rgba = 32,
+}
+
+impl ColorDepth {
+    pub fn bits_per_pixel(self) -> u16 {
+        self as u16
+    }
+
+    pub fn bytes_per_pixel(self) -> usize {
+        self as usize / 8
+    }
+}
+
+impl TryFrom<u16> for ColorDepth {
+    type Error = Error;
+
+    fn try_from(value: u16) -> Result<Self, Self::Error> {
+        match value {
+            8 => Ok(ColorDepth::Indexed),
+            16 => Ok(ColorDepth::Grayscale),
+            32 => Ok(ColorDepth::Rgba),
+            _ => Err(Error::bad_enum(0, "color depth", value as u32)),
+        }
+    }
+}
+
+/// Pixel aspect ratio, `width / height`. Never zero.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PixelRatio {
+    pub width: u8,
+    pub height: u8,
+}
+
+impl PixelRatio {
+    pub const SQUARE: PixelRatio = PixelRatio { width: 1, height: 1 };
+
+    /// Builds a ratio, treating a zero in either field as 1:1 like Aseprite does.
+    pub fn new(width: u8, height: u8) -> Self {
+        if width == 0 || height == 0 {
+            Self::SQUARE
+        } else {
+            PixelRatio { width, height }
+        }
+    }
+
+    pub fn is_square(&self) -> bool {
+        self.width == self.height
+    }
+}
+
+impl Default for PixelRatio {
+    fn default() -> Self {
+        Self::SQUARE
+    }
 }
 
-pub fn parse_header<'a>(input: &'a [u8]) -> Result<(&'a ASEHeader, &'a [u8]), HeaderParseError> {
-    let (header, rest) = ASEHeader::ref_from_prefix(&input).map_err(|_| HeaderParseError::CastError)?;
+/// The editor grid, in sprite pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Grid {
+    pub x: i16,
+    pub y: i16,
+    pub width: u16,
+    pub height: u16,
+}
 
-    Ok((header, rest))
+/// Casts the start of `input` to `T`, failing with [`ErrorKind::Truncated`] at offset 0
+/// when there are not enough bytes.
+pub(crate) fn ref_prefix<T>(input: &[u8]) -> Result<(&T, &[u8]), Error>
+where
+    T: FromBytes + KnownLayout + Immutable,
+{
+    T::ref_from_prefix(input)
+        .map_err(|_| Error::truncated(0, core::mem::size_of::<T>(), input.len()))
+}
+
+pub fn parse_header(input: &[u8]) -> Result<(&ASEHeader, &[u8]), Error> {
+    ref_prefix(input)
 }
 
 
@@ -79,34 +535,364 @@ pub fn parse_header<'a>(input: &'a [u8]) -> Result<(&'a ASEHeader, &'a [u8]), He
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::parser::chunk::CHUNK_HEADER_SIZE;
+    use crate::test_util::{FileBuilder, chunk, external_files, palette, palette_colors, tileset};
+
+    fn sample() -> Vec<u8> {
+        std::fs::read("tests/anim_idle.ase").unwrap()
+    }
+
+    #[test]
+    fn header_fields_are_little_endian() {
+        let mut bytes = [0u8; HEADER_SIZE];
+        bytes[0..4].copy_from_slice(&[0x78, 0x56, 0x34, 0x12]);
+        bytes[4..6].copy_from_slice(&[0xE0, 0xA5]);
+        bytes[6..8].copy_from_slice(&[0x02, 0x01]);
+        bytes[8..10].copy_from_slice(&[0x40, 0x00]);
+        bytes[10..12].copy_from_slice(&[0x00, 0x01]);
+        bytes[12..14].copy_from_slice(&[0x20, 0x00]);
+        bytes[14..18].copy_from_slice(&[0x05, 0x00, 0x00, 0x00]);
+        bytes[18..20].copy_from_slice(&[0x64, 0x00]);
+        bytes[28] = 3;
+        bytes[32..34].copy_from_slice(&[0x00, 0x01]);
+        bytes[34] = 2;
+        bytes[35] = 1;
+        bytes[36..38].copy_from_slice(&[0xFE, 0xFF]);
+        bytes[38..40].copy_from_slice(&[0x03, 0x00]);
+        bytes[40..42].copy_from_slice(&[0x10, 0x00]);
+        bytes[42..44].copy_from_slice(&[0x08, 0x00]);
+
+        let (header, rest) = parse_header(&bytes).unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(header.filesize(), 0x12345678);
+        assert_eq!(header.frames(), 0x0102);
+        assert_eq!(header.width(), 64);
+        assert_eq!(header.height(), 256);
+        assert_eq!(header.depth(), 32);
+        assert_eq!(header.color_depth(), Ok(ColorDepth::Rgba));
+        assert_eq!(header.flags(), HeaderFlags::LAYER_OPACITY_VALID | HeaderFlags::LAYERS_HAVE_UUID);
+        assert_eq!(header.speed(), 100);
+        assert_eq!(header.transparent_index(), 3);
+        assert_eq!(header.num_colors(), 256);
+        assert_eq!((header.pixel_width(), header.pixel_height()), (2, 1));
+        assert_eq!((header.grid_x(), header.grid_y()), (-2, 3));
+        assert_eq!((header.grid_width(), header.grid_height()), (16, 8));
+        assert_eq!(header.pixel_ratio(), PixelRatio { width: 2, height: 1 });
+        assert_eq!(header.grid(), Some(Grid { x: -2, y: 3, width: 16, height: 8 }));
+    }
+
+    #[test]
+    fn header_model() {
+        let data = sample();
+        let r = HeaderReader::try_new(&data).unwrap();
+        let header = r.header();
+        assert_eq!(r.color_depth(), ColorDepth::Indexed);
+        assert_eq!(r.color_depth().bytes_per_pixel(), 1);
+        assert_eq!(header.flags(), HeaderFlags::LAYER_OPACITY_VALID);
+        assert!(header.pixel_ratio().is_square());
+        assert_eq!(header.grid(), Some(Grid { x: 0, y: 0, width: 1, height: 1 }));
+
+        assert_eq!(PixelRatio::new(0, 3), PixelRatio::SQUARE);
+        assert_eq!(PixelRatio::new(3, 0), PixelRatio::SQUARE);
+        assert!(ColorDepth::try_from(24).is_err());
+    }
+
+    #[test]
+    fn try_new_accepts_valid_file() {
+        let data = sample();
+        let r = HeaderReader::try_new(&data).unwrap();
+        assert_eq!(r.header().frames(), 12);
+        assert_eq!(r.frames().count(), 12);
+    }
+
+    #[test]
+    fn try_new_rejects_bad_magic() {
+        let mut data = sample();
+        data[4] = 0;
+        assert!(matches!(HeaderReader::try_new(&data), Err(Error { kind: ErrorKind::BadMagic { found: 0xA500, .. }, offset: 4, .. })));
+    }
 
-    pub fn get_chunk_17<'a>() -> ASEChunkReader<'a>  {
-        let a = std::fs::read("tests/anim_idle.ase").unwrap();
-        let (header, rest) = parse_header(a.leak()).unwrap();
+    #[test]
+    fn try_new_rejects_truncated_file() {
+        let data = sample();
+        assert!(matches!(HeaderReader::try_new(&data[..data.len() - 1]), Err(Error { kind: ErrorKind::TruncatedFile { .. }, .. })));
+        assert!(matches!(HeaderReader::try_new(&data[..64]), Err(Error { kind: ErrorKind::Truncated { needed: 128, available: 64 }, .. })));
+    }
+
+    #[test]
+    fn try_new_rejects_bad_depth() {
+        let mut data = sample();
+        data[12] = 24;
+        assert!(matches!(HeaderReader::try_new(&data), Err(Error { kind: ErrorKind::BadEnumValue { value: 24, .. }, .. })));
+    }
+
+    /// Walks everything reachable from `data`, ignoring errors.
+    fn walk(data: &[u8]) {
+        let Ok(r) = HeaderReader::try_new(data) else { return };
+        for frame in r.frames().flatten() {
+            for chunk in frame.chunks() {
+                if let Ok(ASEChunk::Cel(cel)) = chunk {
+                    let _ = cel.get();
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn truncated_input_never_panics() {
+        let data = sample();
+        for len in 0..data.len() {
+            let mut truncated = data[..len].to_vec();
+            if len >= 4 {
+                // keep the header consistent so truncation is hit inside frames and chunks
+                truncated[..4].copy_from_slice(&(len as u32).to_le_bytes());
+            }
+            walk(&truncated);
+        }
+    }
+
+    #[test]
+    fn corrupted_input_never_panics() {
+        let data = sample();
+        let mut state = 0x2545F4914F6CDD1Du64;
+        let mut next = || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+        for _ in 0..20_000 {
+            let mut corrupted = data.clone();
+            for _ in 0..(next() % 4 + 1) {
+                let at = HEADER_SIZE + (next() as usize % (data.len() - HEADER_SIZE));
+                corrupted[at] = next() as u8;
+            }
+            walk(&corrupted);
+        }
+    }
+
+    #[test]
+    fn chunk_errors_carry_location() {
+        let sample = sample();
+        // first cel of the first frame, after 14 other chunks
+        let frame = HeaderReader::new(&sample).frames().next().unwrap().unwrap();
+        let mut chunks = frame.chunks();
+        let preceding: usize = (0..14).map(|_| chunks.next_raw().unwrap().unwrap().data().len() + CHUNK_HEADER_SIZE).sum();
+        // the layer index, position and opacity come before the cel type
+        let cel_type_at = frame.byte_offset() + FRAME_HEADER_SIZE + preceding + CHUNK_HEADER_SIZE + 7;
+        let mut data = sample.clone();
+        data[cel_type_at] = 9;
+        let r = HeaderReader::try_new(&data).unwrap();
+        let frame = r.frames().next().unwrap().unwrap();
+        let err = frame.chunks().find_map(Result::err).unwrap();
+        assert_eq!(err, Error {
+            kind: ErrorKind::BadEnumValue { field: "cel type", value: 9 },
+            offset: cel_type_at,
+            frame: Some(0),
+            chunk: Some(14),
+            chunk_type: Some(0x2005),
+        });
+        // the broken cel does not stop iteration
+        assert_eq!(frame.chunks().count(), 20);
+    }
+
+    #[test]
+    fn unreadable_chunk_header_has_no_type() {
+        let data = FileBuilder::new().frame_with_counts(100, 2, 2, &[chunk(0x2020, &[0; 4])]).build();
+        let r = HeaderReader::new(&data);
+        let frame = r.frames().next().unwrap().unwrap();
+        let e = frame.chunks().find_map(Result::err).unwrap();
+        assert_eq!((e.frame, e.chunk, e.chunk_type), (Some(0), Some(1), None));
+    }
+
+    #[test]
+    fn try_new_rejects_too_many_frames() {
+        let mut data = sample();
+        data[6..8].copy_from_slice(&0xFFFFu16.to_le_bytes());
+        assert!(matches!(HeaderReader::try_new(&data), Err(Error { kind: ErrorKind::TooManyFrames { .. }, .. })));
+    }
+
+    #[test]
+    fn palette_for_frame_applies_earlier_frames() {
+        let data = FileBuilder::new()
+            .depth(8)
+            .frame(100, &[chunk(0x2019, &palette(3, 0, 2, &palette_colors(&[[0, 0, 0, 0], [22, 18, 54, 255], [255, 255, 255, 255]])))])
+            .frame(100, &[])
+            .frame(100, &[chunk(0x2019, &palette(4, 2, 3, &palette_colors(&[[1, 2, 3, 255], [4, 5, 6, 255]])))])
+            .build();
+        let r = HeaderReader::new(&data);
+        let mut palette = [Rgba::new(9, 9, 9, 9); 8];
+
+        assert_eq!(r.palette_for_frame(1, &mut palette), Ok(3));
+        assert_eq!(palette[1], Rgba::new(22, 18, 54, 255));
+        assert_eq!(palette[2], Rgba::new(255, 255, 255, 255));
+        assert_eq!(palette[3], Rgba::TRANSPARENT);
+
+        assert_eq!(r.palette_for_frame(2, &mut palette), Ok(4));
+        assert_eq!(palette[1], Rgba::new(22, 18, 54, 255));
+        assert_eq!(palette[2], Rgba::new(1, 2, 3, 255));
+        assert_eq!(palette[3], Rgba::new(4, 5, 6, 255));
+
+        let e = r.palette_for_frame(2, &mut palette[..3]).unwrap_err();
+        assert_eq!(e.kind, ErrorKind::BufferTooSmall { needed: 4, available: 3 });
+        assert_eq!((e.frame, e.chunk), (Some(2), Some(0)));
+    }
 
-        // let (fc, rest) = parse_frame(rest).unwrap();
-        // let w: ChunkIterator<'a> = fc.chunks();
-        // // let cptr = Box::leak(Box::new(w));
-        // let cptr = w;
+    #[test]
+    fn palette_size_larger_than_buffer() {
+        // the entries fit, only the declared size doesn't
+        let data = FileBuilder::new()
+            .depth(8)
+            .frame(100, &[chunk(0x2019, &palette(4, 0, 1, &palette_colors(&[[1, 2, 3, 255], [4, 5, 6, 255]])))])
+            .build();
+        let e = HeaderReader::new(&data).palette_for_frame(0, &mut [Rgba::TRANSPARENT; 3]).unwrap_err();
+        assert_eq!(e.kind, ErrorKind::BufferTooSmall { needed: 4, available: 3 });
+        assert_eq!((e.offset, e.frame, e.chunk), (HEADER_SIZE + FRAME_HEADER_SIZE + CHUNK_HEADER_SIZE, Some(0), Some(0)));
+    }
+
+    #[test]
+    fn old_palettes_are_used_without_new_ones() {
+        let data = sample();
+        let r = HeaderReader::new(&data);
+        let mut palette = [Rgba::TRANSPARENT; 256];
+        assert_eq!(r.palette_for_frame(0, &mut palette), Ok(3));
+        assert_eq!(palette[..3], [
+            Rgba::new(0x8b, 0x8b, 0x8b, 255),
+            Rgba::new(22, 18, 54, 255),
+            Rgba::new(0xba, 0xba, 0xba, 255),
+        ]);
+    }
+
+    #[test]
+    fn old_palettes_are_ignored_with_new_ones() {
+        let old = chunk(0x0004, &[1, 0, 0, 2, 1, 1, 1, 2, 2, 2]);
+        let old_six_bit = chunk(0x0011, &[1, 0, 0, 1, 63, 63, 63]);
+        let data = FileBuilder::new()
+            .depth(8)
+            .frame(100, &[old_six_bit.clone(), old.clone()])
+            .frame(100, &[chunk(0x2019, &palette(2, 1, 1, &palette_colors(&[[5, 5, 5, 255]]))), old_six_bit])
+            .build();
+        let r = HeaderReader::new(&data);
+        let mut palette = [Rgba::TRANSPARENT; 4];
+
+        // frame 0 only has old palettes, but the file has a new one later on
+        assert_eq!(r.palette_for_frame(0, &mut palette), Ok(0));
+        assert_eq!(palette, [Rgba::TRANSPARENT; 4]);
+
+        assert_eq!(r.palette_for_frame(1, &mut palette), Ok(2));
+        assert_eq!(palette[..2], [Rgba::TRANSPARENT, Rgba::new(5, 5, 5, 255)]);
+    }
+
+    #[test]
+    fn tags_by_name() {
+        use crate::parser::chunk::tags::LoopDirection;
+
+        let data = sample();
+        let r = HeaderReader::new(&data);
+        assert_eq!(r.tags().unwrap().unwrap().len(), 2);
 
-        // let chunk: ASEChunkContainer<'a> = cptr.get(17).unwrap();
-        // chunk
-        todo!()
+        let idle = r.tag("Idle").unwrap().unwrap();
+        assert_eq!((idle.from, idle.to, idle.direction, idle.repeat), (0, 1, LoopDirection::Forward, 0));
+        let stand = r.tag("stand").unwrap().unwrap();
+        assert_eq!((stand.from, stand.to), (2, 11));
+        assert_eq!(r.tag("walk"), Ok(None));
+
+        let data = FileBuilder::new().frame(100, &[]).build();
+        assert!(HeaderReader::new(&data).tags().unwrap().is_none());
     }
 
+    #[test]
+    fn color_profile() {
+        let data = sample();
+        let r = HeaderReader::new(&data);
+        assert_eq!(r.color_profile().unwrap(), Some(ColorProfile::Srgb { gamma: None }));
+        assert_eq!(HeaderReader::new(&FileBuilder::new().build()).color_profile().unwrap(), None);
+    }
 
-    // #[test]
-    // fn test_read() {
-    //     let a = std::fs::read("tests/anim_idle.ase").unwrap();
-    //     let (header, rest) = parse_header(&a).unwrap();
+    #[test]
+    fn sprite_and_tag_user_data() {
+        use crate::parser::chunk::user_data::PropertyValue;
 
-    //     let ASEFrameContainer(frame , rest) = parse_frame(rest).unwrap();
+        let data = sample();
+        let r = HeaderReader::new(&data);
+        let sprite = r.user_data().unwrap().unwrap();
+        let (key, map) = sprite.properties().next().unwrap();
+        assert_eq!(key, 1);
+        assert_eq!(map.get("camera_layer_name"), Some(PropertyValue::String("cam")));
 
-    //     let c: ChunkIterator = ChunkIterator { ptr: rest, count: frame.num_chunks as usize};
-    //     let chunk = c.get(17).unwrap();
-    //     if let ASEChunk::Unknown(contents) = chunk.get_chunk() {
-    //         println!("{:#x?}", &contents[..10]);
-    //     }
+        let tags = r.tags().unwrap().unwrap();
+        for tag in &tags {
+            assert_eq!(tag.user_data().unwrap().color(), Some(Rgba::new(0, 0, 0, 255)));
+        }
 
-    // }
+        let data = FileBuilder::new().frame(100, &[chunk(0x2020, &[0; 4])]).build();
+        assert!(HeaderReader::new(&data).user_data().unwrap().is_none());
+    }
+
+    #[test]
+    fn tilesets_through_a_resolver() {
+        let image = crate::test_util::zlib(&[42]);
+        let shared = FileBuilder::new().depth(8)
+            .frame(100, &[chunk(0x2023, &tileset(3, 2, 1, 1, 1, &[&(image.len() as u32).to_le_bytes()[..], &image].concat()))])
+            .build();
+        let external = [&5u32.to_le_bytes()[..], &3u32.to_le_bytes()].concat();
+        let level = FileBuilder::new().depth(8)
+            .frame(100, &[
+                chunk(0x2008, &external_files(&[(5, 1, "shared.aseprite"), (6, 0, "colors.aseprite")])),
+                chunk(0x2023, &tileset(0, 1, 1, 1, 1, &external)),
+            ])
+            .build();
+        let r = HeaderReader::new(&level);
+        assert_eq!(r.external_files().unwrap().unwrap().get(5).map(|f| f.name), Some("shared.aseprite"));
+        assert!(r.tileset(0).unwrap().unwrap().compressed_image().is_none());
+
+        let mut resolver = |file: &ExternalFile<'_>| (file.name == "shared.aseprite").then_some(&shared[..]);
+        let tileset = r.resolve_tileset(0, &mut resolver).unwrap().unwrap();
+        assert_eq!(tileset.header.id(), 3);
+        let mut buf = [0u8; 1];
+        assert_eq!(tileset.decompress(ColorDepth::Indexed, &mut buf).unwrap().tile(0).unwrap().pixels, &[42]);
+        assert!(r.resolve_tileset(1, &mut resolver).unwrap().is_none());
+
+        let e = r.external_palette(&mut resolver, &mut [Rgba::TRANSPARENT; 4]).unwrap_err();
+        assert_eq!(e.kind, ErrorKind::MissingExternalFile(6));
+        assert_eq!((e.frame, e.chunk, e.chunk_type), (Some(0), Some(0), Some(0x2008)));
+        let e = r.resolve_tileset(0, &mut |_: &ExternalFile<'_>| None).unwrap_err();
+        assert_eq!(e.kind, ErrorKind::MissingExternalFile(5));
+        assert_eq!((e.frame, e.chunk, e.chunk_type), (Some(0), Some(1), Some(0x2023)));
+    }
+
+    #[test]
+    fn external_palette() {
+        let colors = FileBuilder::new().frame(100, &[chunk(0x2019, &palette(2, 0, 1, &palette_colors(&[[1, 2, 3, 255], [4, 5, 6, 255]])))]).build();
+        let sprite = FileBuilder::new().frame(100, &[chunk(0x2008, &external_files(&[(1, 0, "colors.aseprite")]))]).build();
+        let r = HeaderReader::new(&sprite);
+        let mut palette = [Rgba::TRANSPARENT; 4];
+        assert_eq!(r.external_palette(&mut |_: &ExternalFile<'_>| Some(&colors[..]), &mut palette).unwrap(), Some(2));
+        assert_eq!(palette[1], Rgba::new(4, 5, 6, 255));
+
+        let data = sample();
+        assert_eq!(HeaderReader::new(&data).external_palette(&mut |_: &ExternalFile<'_>| None, &mut palette).unwrap(), None);
+    }
+
+    #[test]
+    fn slices_by_name() {
+        let slice = |name: &str, x: i32| {
+            let payload = [
+                &1u32.to_le_bytes()[..], &0u32.to_le_bytes(), &0u32.to_le_bytes(), &crate::test_util::string(name),
+                &0u32.to_le_bytes(), &x.to_le_bytes(), &0i32.to_le_bytes(), &4u32.to_le_bytes(), &4u32.to_le_bytes(),
+            ].concat();
+            chunk(0x2022, &payload)
+        };
+        let user_data = chunk(0x2020, &[&1u32.to_le_bytes()[..], &crate::test_util::string("solid")].concat());
+        let data = FileBuilder::new().frame(100, &[slice("hitbox", 3), user_data, slice("anchor", 5)]).build();
+        let r = HeaderReader::new(&data);
+
+        let hitbox = r.slice("hitbox").unwrap().unwrap();
+        assert_eq!(hitbox.key_for_frame(0).unwrap().bounds.origin, Point { x: 3, y: 0 });
+        assert_eq!(hitbox.user_data().and_then(|u| u.text()), Some("solid"));
+        let anchor = r.slice("anchor").unwrap().unwrap();
+        assert_eq!(anchor.key_for_frame(7).unwrap().bounds.origin, Point { x: 5, y: 0 });
+        assert!(anchor.user_data().is_none());
+        assert!(r.slice("Hitbox").unwrap().is_none());
+    }
 }
@@ -0,0 +1,73 @@
+use zerocopy::*;
+
+use crate::error::{Error, ErrorKind};
+
+/// Bounds-checked cursor over a chunk payload.
+///
+/// Errors carry the offset relative to the start of the buffer the reader was created with.
+#[derive(Debug, Clone)]
+pub(crate) struct ByteReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        ByteReader { data, pos: 0 }
+    }
+
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+
+    pub fn remaining(&self) -> &'a [u8] {
+        &self.data[self.pos..]
+    }
+
+    pub fn read<T>(&mut self) -> Result<&'a T, Error>
+    where
+        T: FromBytes + KnownLayout + Immutable + Unaligned,
+    {
+        let (value, _) = T::ref_from_prefix(self.remaining())
+            .map_err(|_| Error::truncated(self.pos, core::mem::size_of::<T>(), self.remaining().len()))?;
+        self.pos += core::mem::size_of::<T>();
+        Ok(value)
+    }
+
+    pub fn bytes(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        let rest = self.remaining();
+        if len > rest.len() {
+            return Err(Error::truncated(self.pos, len, rest.len()));
+        }
+        self.pos += len;
+        Ok(&rest[..len])
+    }
+
+    pub fn u8(&mut self) -> Result<u8, Error> {
+        self.read::<u8>().copied()
+    }
+
+    pub fn u16(&mut self) -> Result<u16, Error> {
+        self.read::<U16<LE>>().map(|v| v.get())
+    }
+
+    pub fn u32(&mut self) -> Result<u32, Error> {
+        self.read::<U32<LE>>().map(|v| v.get())
+    }
+
+    pub fn i32(&mut self) -> Result<i32, Error> {
+        self.read::<I32<LE>>().map(|v| v.get())
+    }
+
+    pub fn u64(&mut self) -> Result<u64, Error> {
+        self.read::<U64<LE>>().map(|v| v.get())
+    }
+
+    /// Reads a `STRING`: a `WORD` length followed by that many bytes of UTF-8.
+    pub fn string(&mut self) -> Result<&'a str, Error> {
+        let len = self.u16()? as usize;
+        let start = self.pos;
+        let bytes = self.bytes(len)?;
+        str::from_utf8(bytes).map_err(|_| Error::new(ErrorKind::InvalidUtf8, start))
+    }
+}
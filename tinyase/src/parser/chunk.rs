@@ -1,38 +1,85 @@
-use thiserror::Error;
 use zerocopy::*;
 
+use crate::error::{Error, ErrorKind};
+use crate::parser::{ColorDepth, HeaderFlags};
+use crate::parser::bytes::ByteReader;
+use crate::parser::inflate::{StreamInflater, inflate_exact};
+
+pub mod cel_extra;
+pub mod color_profile;
+pub mod external_files;
 pub mod layer;
+pub mod mask;
+pub mod old_palette;
+pub mod palette;
+pub mod slice;
+pub mod tags;
+pub mod tilemap;
+pub mod tileset;
+pub mod user_data;
+
+/// Size of the `<u32 size><u16 type>` prefix of every chunk.
+pub const CHUNK_HEADER_SIZE: usize = 6;
 
 // ptr Points to start of chunk header, which is <u32 size><u16 type>
+//
+// A chunk that cannot be decoded is reported as an error and iteration moves on to the
+// next chunk. A chunk header that cannot be read ends the iteration after the error.
 #[derive(Debug, Clone)]
 pub struct ChunkIterator<'a> {
     pub ptr: &'a [u8],
     pub remaining: usize,
+    /// Offset of `ptr` from the start of the file, used for error reporting
+    pub(crate) offset: usize,
+    pub(crate) frame: u16,
+    pub(crate) index: u32,
+    /// Flags of the file header, which some chunks depend on
+    pub(crate) flags: HeaderFlags,
+}
+
+impl<'a> ChunkIterator<'a> {
+    /// Returns the next chunk without decoding its payload.
+    ///
+    /// Like [`Iterator::next`], a chunk header that cannot be read ends the iteration
+    /// after the error.
+    pub fn next_raw(&mut self) -> Option<Result<RawChunk<'a>, Error>> {
+        if self.remaining == 0 {
+            return None
+        }
+        self.remaining -= 1;
+        let index = self.index;
+        self.index += 1;
+
+        let reader = match ASEChunkReader::new(self.ptr) {
+            Ok(reader) => reader,
+            Err(e) => {
+                self.remaining = 0;
+                return Some(Err(e.offset_by(self.offset).in_chunk_index(index).in_frame(self.frame)));
+            }
+        };
+        let size = reader.1.len() + CHUNK_HEADER_SIZE;
+        let chunk = RawChunk {
+            chunk_type: reader.0.chunk_type(),
+            data: reader.1,
+            following: &self.ptr[size..],
+            offset: self.offset + CHUNK_HEADER_SIZE,
+            frame: self.frame,
+            index,
+            flags: self.flags,
+        };
+
+        self.ptr = &self.ptr[size..];
+        self.offset += size;
+        Some(Ok(chunk))
+    }
 }
 
 impl<'a> Iterator for ChunkIterator<'a> 
 {
-    type Item = ASEChunk<'a>;
+    type Item = Result<ASEChunk<'a>, Error>;
     
     fn next(&mut self) -> Option<Self::Item> {
-        if self.remaining <= 0 {
-            return None
-        }
-        if let Some((chunk,rest)) = ASEChunkHeader::ref_from_prefix(&self.ptr)
-            .map_err(|_| ChunkHeaderParseError::CastError)
-            .ok() {
-            // #[cfg(test)] {
-            //     let chunk_type = chunk.chunk_type;
-            //     let size = chunk.size;
-            //     println!("Chunk type: {:#x}, size: {}", chunk_type, size);
-            // }
-            let my_resp = ASEChunkReader(chunk, rest);
-            self.ptr = &rest[(chunk.size as usize - 6)..];
-            self.remaining = self.remaining - 1;
-            Some(my_resp.get_chunk())
-        } else {
-            return None;
-        }
+        Some(self.next_raw()?.and_then(|chunk| chunk.decode()))
     }
 
     fn size_hint(&self) -> (usize, Option<usize>) {
@@ -40,12 +87,94 @@ impl<'a> Iterator for ChunkIterator<'a>
     }
 }
 
+/// A chunk whose payload has not been decoded yet, see [`ChunkIterator::next_raw`].
+#[derive(Debug, Clone, Copy)]
+pub struct RawChunk<'a> {
+    chunk_type: u16,
+    data: &'a [u8],
+    /// Rest of the frame after this chunk, where its user data is found
+    following: &'a [u8],
+    offset: usize,
+    frame: u16,
+    index: u32,
+    flags: HeaderFlags,
+}
+
+impl<'a> RawChunk<'a> {
+    pub fn chunk_type(&self) -> u16 {
+        self.chunk_type
+    }
+
+    /// Chunk payload, without the chunk header
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+
+    /// Offset of the payload from the start of the file
+    pub fn byte_offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Index of the frame holding this chunk
+    pub fn frame(&self) -> u16 {
+        self.frame
+    }
+
+    /// Index of this chunk within its frame
+    pub fn index(&self) -> u32 {
+        self.index
+    }
+
+    /// Decodes the payload with the built-in chunk decoders.
+    ///
+    /// The user data chunks following a layer, cel, tags, slice or tileset chunk are attached to it,
+    /// see [`user_data`], and so is the cel extra chunk following a cel.
+    pub fn decode(&self) -> Result<ASEChunk<'a>, Error> {
+        let mut chunk = ASEChunk::new(self.chunk_type, self.data, self.flags).map_err(|e| self.locate(e))?;
+        match &mut chunk {
+            ASEChunk::Layer(layer) => layer.user_data = user_data::following(self.following, false),
+            ASEChunk::Cel(cel) => {
+                cel.user_data = user_data::following(self.following, true);
+                cel.extra = cel_extra::following(self.following);
+            }
+            ASEChunk::Tags(tags) => tags.following = self.following,
+            ASEChunk::Slice(slice) => slice.user_data = user_data::following(self.following, false),
+            ASEChunk::Tileset(tileset) => {
+                let mut run = user_data::UserDataRun::new(self.following);
+                tileset.user_data = run.pop();
+                tileset.tile_user_data = run;
+            }
+            _ => {}
+        }
+        Ok(chunk)
+    }
+
+    /// Adds the location of this chunk to an error whose offset is relative to the payload.
+    pub fn locate(&self, e: Error) -> Error {
+        e.offset_by(self.offset).in_chunk(self.index, self.chunk_type).in_frame(self.frame)
+    }
+}
+
+/// A chunk header together with the chunk payload it describes.
 pub struct ASEChunkReader<'a> (pub &'a ASEChunkHeader, pub &'a [u8]);
 impl<'a> ASEChunkReader<'a> {
-    pub fn get_chunk(&self) -> ASEChunk<'a> {
-        let chunk_size = self.0.size;
-        let data = &self.1[..(chunk_size as usize - 6)];
-        ASEChunk::new(self.0.chunk_type, data)
+    /// Reads the chunk header at the start of `data`, checking that the declared size fits.
+    pub fn new(data: &'a [u8]) -> Result<Self, Error> {
+        let mut reader = ByteReader::new(data);
+        let header = reader.read::<ASEChunkHeader>()?;
+        let size = header.size();
+        if (size as usize) < CHUNK_HEADER_SIZE {
+            return Err(Error::new(ErrorKind::InvalidChunkSize(size), 0));
+        }
+        let available = reader.remaining().len();
+        let payload = reader.bytes(size as usize - CHUNK_HEADER_SIZE)
+            .map_err(|_| Error::new(ErrorKind::TruncatedChunk { declared: size, available: available + CHUNK_HEADER_SIZE }, 0))?;
+        Ok(ASEChunkReader(header, payload))
+    }
+
+    /// Decodes the payload. `flags` are the flags of the file header.
+    pub fn get_chunk(&self, flags: HeaderFlags) -> Result<ASEChunk<'a>, Error> {
+        ASEChunk::new(self.0.chunk_type(), self.1, flags)
     }
 }
 
@@ -53,13 +182,34 @@ impl<'a> ASEChunkReader<'a> {
 pub enum ASEChunk<'a> {
     Unknown(u16, &'a[u8]),
     Cel(CelContainer<'a>),
+    CelExtra(CelExtra),
+    ColorProfile(ColorProfile<'a>),
+    ExternalFiles(ExternalFiles<'a>),
     Layer(Layer<'a>),
+    Mask(Mask<'a>),
+    Palette(Palette<'a>),
+    OldPalette(OldPalette<'a>),
+    Tags(Tags<'a>),
+    UserData(UserData<'a>),
+    Slice(Slice<'a>),
+    Tileset(Tileset<'a>),
 }
 
 #[cfg(test)]
     use std::fmt::Display;
 
+use crate::parser::chunk::cel_extra::{CelExtra, PreciseBounds};
+use crate::parser::chunk::color_profile::ColorProfile;
+use crate::parser::chunk::external_files::ExternalFiles;
 use crate::parser::chunk::layer::Layer;
+use crate::parser::chunk::mask::Mask;
+use crate::parser::chunk::old_palette::OldPalette;
+use crate::parser::chunk::palette::Palette;
+use crate::parser::chunk::slice::Slice;
+use crate::parser::chunk::tags::Tags;
+use crate::parser::chunk::tilemap::{TilemapCel, TilemapHeader};
+use crate::parser::chunk::tileset::Tileset;
+use crate::parser::chunk::user_data::UserData;
 
 #[cfg(test)]
     impl Display for ASEChunk<'_> {
@@ -67,32 +217,61 @@ use crate::parser::chunk::layer::Layer;
             match self {
                 ASEChunk::Unknown(t, _) => write!(f, "Unknown Chunk Type: {:#x}", t),
                 ASEChunk::Cel(_) => write!(f, "Cel Chunk"),
+                ASEChunk::CelExtra(_) => write!(f, "Cel Extra Chunk"),
+                ASEChunk::ColorProfile(_) => write!(f, "Color Profile Chunk"),
+                ASEChunk::ExternalFiles(_) => write!(f, "External Files Chunk"),
                 ASEChunk::Layer(_) => write!(f, "Layer Chunk"),
+                ASEChunk::Mask(_) => write!(f, "Mask Chunk"),
+                ASEChunk::Palette(_) => write!(f, "Palette Chunk"),
+                ASEChunk::OldPalette(_) => write!(f, "Old Palette Chunk"),
+                ASEChunk::Tags(_) => write!(f, "Tags Chunk"),
+                ASEChunk::UserData(_) => write!(f, "User Data Chunk"),
+                ASEChunk::Slice(_) => write!(f, "Slice Chunk"),
+                ASEChunk::Tileset(_) => write!(f, "Tileset Chunk"),
             }
         }
     }
 
 impl<'a> ASEChunk<'a> {
-    pub(super) fn new(chunk_type: u16, data: &'a[u8]) -> Self {
-        match chunk_type {
-            0x2004 => ASEChunk::Layer(Layer::new(data)),
-            0x2005 => ASEChunk::Cel(chunk_cel(data)),
+    /// Decodes a chunk payload, laid out according to the header `flags`.
+    ///
+    /// Decoders check the whole payload, so the iterators over the entries of a decoded
+    /// chunk treat a read error as the end of the entries.
+    pub(crate) fn new(chunk_type: u16, data: &'a[u8], flags: HeaderFlags) -> Result<Self, Error> {
+        Ok(match chunk_type {
+            0x0004 | 0x0011 => ASEChunk::OldPalette(OldPalette::new(chunk_type, data)?),
+            0x2004 => ASEChunk::Layer(Layer::new(data, flags)?),
+            0x2005 => ASEChunk::Cel(chunk_cel(data)?),
+            0x2006 => ASEChunk::CelExtra(CelExtra::new(data)?),
+            0x2007 => ASEChunk::ColorProfile(ColorProfile::new(data)?),
+            0x2008 => ASEChunk::ExternalFiles(ExternalFiles::new(data)?),
+            0x2016 => ASEChunk::Mask(Mask::new(data)?),
+            0x2018 => ASEChunk::Tags(Tags::new(data)?),
+            0x2019 => ASEChunk::Palette(Palette::new(data)?),
+            0x2020 => ASEChunk::UserData(UserData::new(data)?),
+            0x2022 => ASEChunk::Slice(Slice::new(data)?),
+            0x2023 => ASEChunk::Tileset(Tileset::new(data)?),
             _ => ASEChunk::Unknown(chunk_type, data),
-        }
+        })
     }
 }
 
-#[derive(Debug, FromBytes, KnownLayout, Immutable)]
-#[repr(packed)]
+#[derive(Debug, FromBytes, KnownLayout, Immutable, Unaligned)]
+#[repr(C)]
 pub struct ASEChunkHeader {
-    pub size: u32,
-    pub chunk_type: u16,
+    size: U32<LE>,
+    chunk_type: U16<LE>,
 }
 
-#[derive(Error, Debug, Clone)]
-pub enum ChunkHeaderParseError {
-    #[error("Cast error")]
-    CastError,
+impl ASEChunkHeader {
+    /// Size of the chunk in bytes, including this header
+    pub fn size(&self) -> u32 {
+        self.size.get()
+    }
+
+    pub fn chunk_type(&self) -> u16 {
+        self.chunk_type.get()
+    }
 }
 
 pub trait NextResult<'a> {
@@ -102,59 +281,136 @@ pub trait NextResult<'a> {
 }
 
 
-#[derive(Debug, Unaligned, TryFromBytes, KnownLayout, Immutable)]
-#[repr(packed)]
+#[derive(Debug, Unaligned, FromBytes, KnownLayout, Immutable)]
+#[repr(C)]
 pub struct CelHeader {
-    pub layer_index: u16,
-    pub point_x: i16,
-    pub point_y: i16,
-    pub opacity: u8,
-    pub cel_type: u16,
-    pub z_index: i16,
+    layer_index: U16<LE>,
+    point_x: I16<LE>,
+    point_y: I16<LE>,
+    opacity: u8,
+    cel_type: U16<LE>,
+    z_index: I16<LE>,
     _unused_1: [u8; 5],
 }
 
-fn chunk_cel<'a>(ptr: &'a[u8]) -> CelContainer<'a>{
-    let (h, p) = CelHeader::try_ref_from_prefix(ptr).unwrap();
-    CelContainer { cel_header: h, ptr: p }
+impl CelHeader {
+    pub fn layer_index(&self) -> u16 {
+        self.layer_index.get()
+    }
+
+    pub fn point_x(&self) -> i16 {
+        self.point_x.get()
+    }
+
+    pub fn point_y(&self) -> i16 {
+        self.point_y.get()
+    }
+
+    pub fn opacity(&self) -> u8 {
+        self.opacity
+    }
+
+    pub fn cel_type(&self) -> u16 {
+        self.cel_type.get()
+    }
+
+    pub fn z_index(&self) -> i16 {
+        self.z_index.get()
+    }
+}
+
+fn chunk_cel(ptr: &[u8]) -> Result<CelContainer<'_>, Error> {
+    let mut reader = ByteReader::new(ptr);
+    let cel_header = reader.read::<CelHeader>()?;
+    let cel_type = cel_header.cel_type();
+    let body_size = match cel_type {
+        0 | 2 => core::mem::size_of::<RawImageHeader>(),
+        1 => 2,
+        3 => core::mem::size_of::<TilemapHeader>(),
+        _ => return Err(Error::bad_enum(7, "cel type", cel_type as u32)),
+    };
+    let rest = reader.remaining();
+    if rest.len() < body_size {
+        return Err(Error::truncated(reader.position(), body_size, rest.len()));
+    }
+    if cel_type == 3 {
+        TilemapCel::new(rest).map_err(|e| e.offset_by(reader.position()))?;
+    }
+    Ok(CelContainer { cel_header, ptr: rest, user_data: None, extra: None })
 }
 
 #[derive(Debug)]
 pub struct CelContainer<'a> {
     pub cel_header: &'a CelHeader,
     ptr: &'a [u8],
+    user_data: Option<UserData<'a>>,
+    extra: Option<CelExtra>,
 }
 
 #[derive(Debug)]
 pub enum CelData<'a> {
     Raw(RawImageDataContainer<'a>),
     Linked(u16),
+    Compressed(CompressedImage<'a>),
+    Tilemap(TilemapCel<'a>),
 }
 
 impl<'a> CelContainer<'a> {
-    pub fn get(&'a self) -> CelData<'a> {
+    /// User data of this cel, see [`user_data`].
+    pub fn user_data(&self) -> Option<&UserData<'a>> {
+        self.user_data.as_ref()
+    }
+
+    /// Sub-pixel position and size from the cel extra chunk, when the cel was read from
+    /// a whole frame and was scaled in real time.
+    pub fn precise_bounds(&self) -> Option<PreciseBounds> {
+        self.extra?.precise_bounds()
+    }
+
+    /// Decodes the cel body. The cel type and body size are checked when the chunk is
+    /// read, error offsets are relative to the start of the cel data after the [`CelHeader`].
+    pub fn get(&'a self) -> Result<CelData<'a>, Error> {
         let header = self.cel_header;
-        let cel_type = header.cel_type;
+        let cel_type = header.cel_type();
+        let mut reader = ByteReader::new(self.ptr);
         match cel_type {
             0 => {
-                let a = RawImageHeader::ref_from_prefix(self.ptr).unwrap();
-                let b = RawImageDataContainer{parent: self, header: a.0, ptr: a.1};
-                CelData::Raw(b)
+                let header = reader.read::<RawImageHeader>()?;
+                Ok(CelData::Raw(RawImageDataContainer{parent: self, header, ptr: reader.remaining()}))
             },
-            1 => {
-                let (z, _) = U16::<LittleEndian>::ref_from_prefix(self.ptr).unwrap();
-                CelData::Linked(z.get())
-            }
-            _ => panic!("Unsupported cel_type: {}", cel_type)
+            1 => Ok(CelData::Linked(reader.u16()?)),
+            2 => {
+                let header = reader.read::<RawImageHeader>()?;
+                Ok(CelData::Compressed(CompressedImage{parent: self, header, data: reader.remaining()}))
+            },
+            3 => Ok(CelData::Tilemap(TilemapCel::new(self.ptr)?)),
+            _ => Err(Error::bad_enum(0, "cel type", cel_type as u32)),
         }
     }
 }
 
-#[derive(Debug, FromBytes, Immutable, KnownLayout)]
-#[repr(packed)]
+#[derive(Debug, FromBytes, Immutable, KnownLayout, Unaligned)]
+#[repr(C)]
 pub struct RawImageHeader {
-    pub width: u16,
-    pub height: u16,
+    width: U16<LE>,
+    height: U16<LE>,
+}
+
+impl RawImageHeader {
+    pub fn width(&self) -> u16 {
+        self.width.get()
+    }
+
+    pub fn height(&self) -> u16 {
+        self.height.get()
+    }
+}
+
+/// Size in bytes of an image of `width * height` pixels, which can overflow on 32 bit targets.
+pub(crate) fn image_len(width: u16, height: u16, bytes_per_pixel: usize) -> Result<usize, Error> {
+    (width as usize).checked_mul(height as usize)
+        .and_then(|pixels| pixels.checked_mul(bytes_per_pixel))
+        .ok_or(Error::new(ErrorKind::ImageTooLarge, 0))
 }
 
 #[derive(Debug, Clone)]
@@ -164,21 +420,232 @@ pub struct RawImageDataContainer<'a> {
     pub ptr: &'a [u8],
 }
 
+/// Body of a compressed image cel (cel type 2), the default for files saved by Aseprite.
+#[derive(Debug, Clone)]
+pub struct CompressedImage<'a> {
+    pub parent: &'a CelContainer<'a>,
+    pub header: &'a RawImageHeader,
+    data: &'a [u8],
+}
+
+impl<'a> CompressedImage<'a> {
+    /// The zlib compressed pixels
+    pub fn compressed_data(&self) -> &'a [u8] {
+        self.data
+    }
+
+    /// Size in bytes of the decompressed image, `width * height * bytes_per_pixel`.
+    /// Fails with [`ErrorKind::ImageTooLarge`] when that doesn't fit in a `usize`.
+    pub fn required_len(&self, depth: ColorDepth) -> Result<usize, Error> {
+        image_len(self.header.width(), self.header.height(), depth.bytes_per_pixel())
+    }
+
+    /// Decompresses the pixels into `buf`, which must hold at least
+    /// [`CompressedImage::required_len`] bytes. `depth` is the color depth of the sprite.
+    ///
+    /// The result reads like the body of a raw cel.
+    pub fn decompress<'b>(&self, depth: ColorDepth, buf: &'b mut [u8]) -> Result<RawImageDataContainer<'b>, Error>
+    where
+        'a: 'b,
+    {
+        let len = self.required_len(depth)?;
+        if buf.len() < len {
+            return Err(Error::new(ErrorKind::BufferTooSmall { needed: len, available: buf.len() }, 0));
+        }
+        let buf = &mut buf[..len];
+        inflate_exact(self.data, buf)?;
+        Ok(RawImageDataContainer { parent: self.parent, header: self.header, ptr: buf })
+    }
+
+    /// Decompresses the pixels one row at a time, for images that don't fit in memory.
+    ///
+    /// `window` keeps the recent output the compressed stream refers back to. It needs
+    /// the window size declared by the stream, 32 KiB for files saved by Aseprite, or
+    /// the whole image size rounded up to a power of two when that is smaller.
+    pub fn rows<'w>(&self, depth: ColorDepth, window: &'w mut [u8]) -> Result<CompressedRows<'a, 'w>, Error> {
+        let inflater = StreamInflater::new(self.data, self.required_len(depth)?, window)?;
+        Ok(CompressedRows {
+            header: self.header,
+            inflater,
+            row_len: self.header.width() as usize * depth.bytes_per_pixel(),
+            remaining: self.header.height(),
+            finished: false,
+        })
+    }
+}
+
+/// Rows of a [`CompressedImage`], decompressed on demand, see [`CompressedImage::rows`].
+pub struct CompressedRows<'a, 'w> {
+    pub header: &'a RawImageHeader,
+    inflater: StreamInflater<'a, 'w>,
+    row_len: usize,
+    remaining: u16,
+    finished: bool,
+}
+
+impl CompressedRows<'_, '_> {
+    /// Size in bytes of one row
+    pub fn row_len(&self) -> usize {
+        self.row_len
+    }
+
+    /// Decompresses the next row into the start of `row`, returning the filled part.
+    ///
+    /// Returns `None` after the last row, once the end of the stream has been checked.
+    pub fn next_row<'r>(&mut self, row: &'r mut [u8]) -> Result<Option<&'r mut [u8]>, Error> {
+        if self.remaining == 0 {
+            if !self.finished {
+                self.finished = true;
+                self.inflater.finish()?;
+            }
+            return Ok(None);
+        }
+        let available = row.len();
+        let row = row.get_mut(..self.row_len)
+            .ok_or(Error::new(ErrorKind::BufferTooSmall { needed: self.row_len, available }, 0))?;
+        self.inflater.read_exact(row)?;
+        self.remaining -= 1;
+        Ok(Some(row))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn chunk_and_cel_headers_are_little_endian() {
+        let bytes = [
+            // chunk header: size 0x2A, type 0x2005
+            0x2A, 0x00, 0x00, 0x00, 0x05, 0x20,
+            // cel header
+            0x02, 0x01, 0xFF, 0xFF, 0x00, 0x80, 0x7F, 0x00, 0x00, 0xFE, 0xFF, 0, 0, 0, 0, 0,
+            // raw image header and 16 pixels
+            0x04, 0x00, 0x04, 0x00,
+            1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16,
+        ];
+        let reader = ASEChunkReader::new(&bytes).unwrap();
+        assert_eq!(reader.0.size(), 0x2A);
+        assert_eq!(reader.0.chunk_type(), 0x2005);
+
+        let Ok(ASEChunk::Cel(cel)) = reader.get_chunk(HeaderFlags::empty()) else { panic!("not a cel") };
+        let h = cel.cel_header;
+        assert_eq!(h.layer_index(), 0x0102);
+        assert_eq!(h.point_x(), -1);
+        assert_eq!(h.point_y(), i16::MIN);
+        assert_eq!(h.opacity(), 0x7F);
+        assert_eq!(h.cel_type(), 0);
+        assert_eq!(h.z_index(), -2);
+
+        let Ok(CelData::Raw(raw)) = cel.get() else { panic!("not a raw cel") };
+        assert_eq!((raw.header.width(), raw.header.height()), (4, 4));
+        assert_eq!(raw.ptr.len(), 16);
+    }
+
+    #[test]
+    fn compressed_cels() {
+        let pixels: std::vec::Vec<u8> = (0..12).collect();
+        let data = [
+            &[0u8; 7][..], &2u16.to_le_bytes(), &[0; 7],
+            &3u16.to_le_bytes(), &2u16.to_le_bytes(), &crate::test_util::zlib(&pixels),
+        ].concat();
+        let Ok(ASEChunk::Cel(cel)) = ASEChunk::new(0x2005, &data, HeaderFlags::empty()) else { panic!("not a cel") };
+        let Ok(CelData::Compressed(image)) = cel.get() else { panic!("not a compressed cel") };
+        assert_eq!(image.required_len(ColorDepth::Grayscale), Ok(12));
+
+        let mut buf = [0u8; 16];
+        let raw = image.decompress(ColorDepth::Grayscale, &mut buf).unwrap();
+        assert_eq!((raw.header.width(), raw.header.height()), (3, 2));
+        assert_eq!(raw.ptr, &pixels[..]);
+
+        let e = image.decompress(ColorDepth::Rgba, &mut buf).unwrap_err();
+        assert_eq!(e.kind, ErrorKind::BufferTooSmall { needed: 24, available: 16 });
+        let e = image.decompress(ColorDepth::Indexed, &mut buf).unwrap_err();
+        assert_eq!(e.kind, ErrorKind::ImageSizeMismatch { expected: 6 });
+    }
+
+    #[test]
+    fn image_len_overflow() {
+        assert_eq!(image_len(3, 2, 4), Ok(24));
+        assert_eq!(image_len(u16::MAX, u16::MAX, usize::MAX / 2).unwrap_err().kind, ErrorKind::ImageTooLarge);
+    }
+
+    #[test]
+    fn compressed_rows() {
+        let pixels: std::vec::Vec<u8> = (0..200u32).map(|i| (i * 7 % 256) as u8).collect();
+        let data = [
+            &[0u8; 7][..], &2u16.to_le_bytes(), &[0; 7],
+            &10u16.to_le_bytes(), &5u16.to_le_bytes(), &crate::test_util::zlib(&pixels),
+        ].concat();
+        let Ok(ASEChunk::Cel(cel)) = ASEChunk::new(0x2005, &data, HeaderFlags::empty()) else { panic!("not a cel") };
+        let Ok(CelData::Compressed(image)) = cel.get() else { panic!("not a compressed cel") };
+
+        // 200 bytes of output only need a 256 byte window
+        let mut window = [0u8; 256];
+        let mut rows = image.rows(ColorDepth::Rgba, &mut window).unwrap();
+        assert_eq!(rows.row_len(), 40);
+        let mut row = [0u8; 64];
+        for expected in pixels.chunks(40) {
+            assert_eq!(rows.next_row(&mut row).unwrap().unwrap(), expected);
+        }
+        assert!(rows.next_row(&mut row).unwrap().is_none());
+
+        let e = image.rows(ColorDepth::Rgba, &mut window[..128]).err().unwrap();
+        assert_eq!(e.kind, ErrorKind::BufferTooSmall { needed: 256, available: 128 });
+        let mut rows = image.rows(ColorDepth::Rgba, &mut window).unwrap();
+        let e = rows.next_row(&mut row[..39]).unwrap_err();
+        assert_eq!(e.kind, ErrorKind::BufferTooSmall { needed: 40, available: 39 });
+        let mut rows = image.rows(ColorDepth::Grayscale, &mut window).unwrap();
+        for _ in 0..5 {
+            rows.next_row(&mut row).unwrap();
+        }
+        assert_eq!(rows.next_row(&mut row).unwrap_err().kind, ErrorKind::ImageSizeMismatch { expected: 100 });
+    }
+
+    #[test]
+    fn tilemap_cels() {
+        let cel = |bits: u16| [
+            &[0u8; 7][..], &3u16.to_le_bytes(), &[0; 7],
+            &1u16.to_le_bytes(), &1u16.to_le_bytes(), &bits.to_le_bytes(), &u32::MAX.to_le_bytes(), &[0; 22],
+            &crate::test_util::zlib(&9u32.to_le_bytes()),
+        ].concat();
+        let data = cel(32);
+        let Ok(ASEChunk::Cel(cel_chunk)) = ASEChunk::new(0x2005, &data, HeaderFlags::empty()) else { panic!("not a cel") };
+        let Ok(CelData::Tilemap(tilemap)) = cel_chunk.get() else { panic!("not a tilemap cel") };
+        let mut buf = [0u8; 4];
+        assert_eq!(tilemap.decompress(&mut buf).unwrap().tile(0, 0).map(|t| t.id), Some(9));
+
+        let e = ASEChunk::new(0x2005, &cel(7), HeaderFlags::empty()).unwrap_err();
+        assert_eq!((e.kind, e.offset), (ErrorKind::BadEnumValue { field: "bits per tile", value: 7 }, 16 + 4));
+    }
+
+    #[test]
+    fn chunk_size_is_checked() {
+        let too_small = [0x05, 0x00, 0x00, 0x00, 0x05, 0x20];
+        assert_eq!(ASEChunkReader::new(&too_small).err().unwrap().kind, ErrorKind::InvalidChunkSize(5));
+
+        let too_large = [0x07, 0x00, 0x00, 0x00, 0x05, 0x20];
+        assert_eq!(
+            ASEChunkReader::new(&too_large).err().unwrap().kind,
+            ErrorKind::TruncatedChunk { declared: 7, available: 6 }
+        );
+    }
+}
+
 #[cfg(feature = "embedded_graphics")]  
 pub mod embedded_graphics_impl {
     use super::*;
     use embedded_graphics::pixelcolor::raw::RawU2;
-    use embedded_graphics::pixelcolor::{BinaryColor, Gray2};
     use embedded_graphics::prelude::*;
     use embedded_graphics::primitives::Rectangle;
 
     
     impl From<&RawImageDataContainer<'_>> for Rectangle {
         fn from(value: &RawImageDataContainer) -> Self {
-            let x = value.parent.cel_header.point_x as i32;
-            let y = value.parent.cel_header.point_y as i32;
-            let width = value.header.width as u32;
-            let height = value.header.height as u32;
+            let x = value.parent.cel_header.point_x() as i32;
+            let y = value.parent.cel_header.point_y() as i32;
+            let width = value.header.width() as u32;
+            let height = value.header.height() as u32;
             Rectangle::new(
                 Point::new(x, y),
                 Size::new(width, height)
@@ -195,7 +662,7 @@ pub mod embedded_graphics_impl {
             PixelIterator::new(self.clone())
             // PixelIterator {
             //     idc: self.clone(),
-            //     remaining: (self.header.width as usize) * (self.header.height as usize),
+            //     remaining: (self.header.width() as usize) * (self.header.height() as usize),
             // }
         }
 
@@ -224,7 +691,7 @@ pub mod embedded_graphics_impl {
         fn next(&mut self) -> Option<Self::Item> {
             // todo!();
 
-            if self.idx >= (self.idc.header.width as usize) * (self.idc.header.height as usize) {
+            if self.idx >= (self.idc.header.width() as usize) * (self.idc.header.height() as usize) {
                 return None;
             }
 
@@ -239,6 +706,46 @@ pub mod embedded_graphics_impl {
         }
     }
 
+    impl From<&CompressedImage<'_>> for Rectangle {
+        fn from(value: &CompressedImage) -> Self {
+            let x = value.parent.cel_header.point_x() as i32;
+            let y = value.parent.cel_header.point_y() as i32;
+            Rectangle::new(
+                Point::new(x, y),
+                Size::new(value.header.width() as u32, value.header.height() as u32)
+            )
+        }
+    }
+
+    /// Like [`PixelIterator`], but decompresses one row at a time into `row`, which must
+    /// hold [`CompressedRows::row_len`] bytes. Iteration ends early on an error.
+    pub struct CompressedPixelIterator<'a, 'w, 'r> {
+        rows: CompressedRows<'a, 'w>,
+        row: &'r mut [u8],
+        len: usize,
+        idx: usize,
+    }
+
+    impl<'a, 'w, 'r> CompressedPixelIterator<'a, 'w, 'r> {
+        pub fn new(rows: CompressedRows<'a, 'w>, row: &'r mut [u8]) -> Self {
+            CompressedPixelIterator { rows, row, len: 0, idx: 0 }
+        }
+    }
+
+    impl Iterator for CompressedPixelIterator<'_, '_, '_> {
+        type Item = AlphaBinaryColor;
+
+        fn next(&mut self) -> Option<Self::Item> {
+            if self.idx >= self.len {
+                self.len = self.rows.next_row(self.row).ok()??.len();
+                self.idx = 0;
+            }
+            let ret = AlphaBinaryColor(RawU2::new(*self.row.get(self.idx)?));
+            self.idx += 1;
+            Some(ret)
+        }
+    }
+
     #[derive(PartialEq, Eq, Clone, Copy, Debug)]
     pub struct AlphaBinaryColor(RawU2);
 
@@ -267,14 +774,45 @@ pub mod embedded_graphics_impl {
 
 #[cfg(all(test, feature = "embedded_graphics"))]
 mod embedded_graphics_test {
-    use embedded_graphics::{framebuffer::{Framebuffer, buffer_size}, pixelcolor::{self, Gray2, raw::RawU2}};
+    use embedded_graphics::{draw_target::DrawTarget, framebuffer::{Framebuffer, buffer_size}, pixelcolor::raw::{BigEndian, RawU2}, primitives::Rectangle};
 
-    use crate::parser::chunk::embedded_graphics_impl::AlphaBinaryColor;
+    use crate::parser::{HeaderReader, chunk::embedded_graphics_impl::{AlphaBinaryColor, CompressedPixelIterator}};
 
     use super::*;
 
     #[test]
     fn test_pixel_iterator() {
-        let mut back_buffer: Framebuffer<AlphaBinaryColor, RawU2, BigEndian, 32, 32, {buffer_size::<AlphaBinaryColor>(320, 240)}> = Framebuffer::new();
+        let mut back_buffer: Framebuffer<AlphaBinaryColor, RawU2, BigEndian, 32, 32, {buffer_size::<AlphaBinaryColor>(32, 32)}> = Framebuffer::new();
+
+        let v = std::fs::read("tests/anim_idle.ase").unwrap();
+        let r = HeaderReader::new(&v);
+        let frame_1 = r.frames().next().unwrap().unwrap();
+        for chunk in frame_1.chunks() {
+            if let Ok(ASEChunk::Cel(cel)) = chunk
+                && let Ok(CelData::Raw(raw)) = cel.get()
+            {
+                let area = Rectangle::from(&raw);
+                assert_eq!(raw.into_iter().count(), area.size.width as usize * area.size.height as usize);
+                back_buffer.fill_contiguous(&area, &raw).unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn compressed_pixel_iterator() {
+        let pixels: std::vec::Vec<u8> = (0..48u8).map(|i| i % 3).collect();
+        let data = [
+            &[0u8; 7][..], &2u16.to_le_bytes(), &[0; 7],
+            &8u16.to_le_bytes(), &6u16.to_le_bytes(), &crate::test_util::zlib(&pixels),
+        ].concat();
+        let Ok(ASEChunk::Cel(cel)) = ASEChunk::new(0x2005, &data, HeaderFlags::empty()) else { panic!("not a cel") };
+        let Ok(CelData::Compressed(image)) = cel.get() else { panic!("not a compressed cel") };
+        assert_eq!(Rectangle::from(&image).size.width, 8);
+
+        let mut window = [0u8; 64];
+        let mut row = [0u8; 8];
+        let rows = image.rows(ColorDepth::Indexed, &mut window).unwrap();
+        let streamed = CompressedPixelIterator::new(rows, &mut row);
+        assert!(streamed.eq(pixels.iter().map(|&p| AlphaBinaryColor::from(RawU2::new(p)))));
     }
 }
\ No newline at end of file
@@ -0,0 +1,124 @@
+use bitflags::bitflags;
+use zerocopy::*;
+
+use crate::error::Error;
+use crate::parser::bytes::ByteReader;
+use crate::parser::chunk::{ASEChunkReader, CHUNK_HEADER_SIZE};
+use crate::parser::types::Fixed;
+
+bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct CelExtraFlags : u32 {
+        const PRECISE_BOUNDS = 1;
+    }
+}
+
+#[derive(Debug, FromBytes, KnownLayout, Immutable, Unaligned)]
+#[repr(C)]
+struct CelExtraHeader {
+    flags: U32<LE>,
+    x: I32<LE>,
+    y: I32<LE>,
+    width: I32<LE>,
+    height: I32<LE>,
+    _reserved: [u8; 16],
+}
+
+/// Position and size of a cel scaled in real time, in sprite pixels.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PreciseBounds {
+    pub x: Fixed,
+    pub y: Fixed,
+    pub width: Fixed,
+    pub height: Fixed,
+}
+
+/// Cel extra chunk (0x2006), which follows the cel it belongs to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CelExtra {
+    pub flags: CelExtraFlags,
+    bounds: PreciseBounds,
+}
+
+impl CelExtra {
+    pub fn new(data: &[u8]) -> Result<Self, Error> {
+        let header = ByteReader::new(data).read::<CelExtraHeader>()?;
+        Ok(CelExtra {
+            flags: CelExtraFlags::from_bits_truncate(header.flags.get()),
+            bounds: PreciseBounds {
+                x: Fixed::from_bits(header.x.get()),
+                y: Fixed::from_bits(header.y.get()),
+                width: Fixed::from_bits(header.width.get()),
+                height: Fixed::from_bits(header.height.get()),
+            },
+        })
+    }
+
+    /// The precise bounds, when the flags say they are set.
+    pub fn precise_bounds(&self) -> Option<PreciseBounds> {
+        self.flags.contains(CelExtraFlags::PRECISE_BOUNDS).then_some(self.bounds)
+    }
+}
+
+/// The cel extra chunk following a cel, before or after the cel's user data.
+pub(crate) fn following(mut rest: &[u8]) -> Option<CelExtra> {
+    loop {
+        let reader = ASEChunkReader::new(rest).ok()?;
+        match reader.0.chunk_type() {
+            0x2006 => return CelExtra::new(reader.1).ok(),
+            0x2020 => rest = &rest[CHUNK_HEADER_SIZE + reader.1.len()..],
+            _ => return None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::error::ErrorKind;
+    use crate::parser::HeaderReader;
+    use crate::parser::chunk::ASEChunk;
+    use crate::test_util::{FileBuilder, chunk};
+    use std::vec::Vec;
+
+    fn extra(flags: u32, bounds: [i32; 4]) -> Vec<u8> {
+        let bounds: Vec<u8> = bounds.iter().flat_map(|v| v.to_le_bytes()).collect();
+        [&flags.to_le_bytes()[..], &bounds, &[0; 16]].concat()
+    }
+
+    #[test]
+    fn precise_bounds() {
+        let e = CelExtra::new(&extra(1, [0x0001_8000, -0x0000_4000, 10 << 16, 0x0002_0001])).unwrap();
+        let bounds = e.precise_bounds().unwrap();
+        assert_eq!((bounds.x.to_f32(), bounds.y.to_f32()), (1.5, -0.25));
+        assert_eq!((bounds.width.round(), bounds.height.floor()), (10, 2));
+
+        let unset = CelExtra::new(&extra(0, [1, 2, 3, 4])).unwrap();
+        assert_eq!(unset.precise_bounds(), None);
+
+        let e = CelExtra::new(&[0; 35]).unwrap_err();
+        assert_eq!(e.kind, ErrorKind::Truncated { needed: 36, available: 35 });
+    }
+
+    #[test]
+    fn attached_to_the_preceding_cel() {
+        let cel = [&[0u8; 7][..], &1u16.to_le_bytes(), &[0; 7], &0u16.to_le_bytes()].concat();
+        let data = FileBuilder::new()
+            .frame(100, &[
+                chunk(0x2005, &cel),
+                chunk(0x2020, &[0; 4]),
+                chunk(0x2006, &extra(1, [3 << 16, 4 << 16, 1 << 16, 1 << 16])),
+                chunk(0x2005, &cel),
+            ])
+            .build();
+        let r = HeaderReader::new(&data);
+        let frame = r.frames().next().unwrap().unwrap();
+        let cels: Vec<_> = frame.chunks().filter_map(|c| match c {
+            Ok(ASEChunk::Cel(cel)) => Some(cel),
+            _ => None,
+        }).collect();
+        assert_eq!(cels[0].precise_bounds().map(|b| (b.x.floor(), b.y.floor())), Some((3, 4)));
+        assert!(cels[0].user_data().is_some());
+        assert_eq!(cels[1].precise_bounds(), None);
+    }
+}
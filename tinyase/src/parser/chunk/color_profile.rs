@@ -0,0 +1,128 @@
+use zerocopy::*;
+
+use crate::error::Error;
+use crate::parser::bytes::ByteReader;
+use crate::parser::chunk::palette::Rgba;
+use crate::parser::types::Fixed;
+
+#[derive(Debug, FromBytes, KnownLayout, Immutable, Unaligned)]
+#[repr(C)]
+struct ColorProfileHeader {
+    profile_type: U16<LE>,
+    flags: U16<LE>,
+    gamma: I32<LE>,
+    _reserved: [u8; 8],
+}
+
+/// Flag telling that the profile uses the fixed gamma of the chunk.
+const FIXED_GAMMA: u16 = 1;
+
+/// Color profile chunk (0x2007), how the pixel values of the sprite are to be read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorProfile<'a> {
+    /// Pixel values are used as they are
+    None,
+    /// sRGB, or values with a fixed gamma when `gamma` is set. A gamma of 1.0 is linear sRGB.
+    Srgb { gamma: Option<Fixed> },
+    /// An embedded ICC profile
+    Icc(&'a [u8]),
+}
+
+impl<'a> ColorProfile<'a> {
+    pub fn new(data: &'a [u8]) -> Result<Self, Error> {
+        let mut reader = ByteReader::new(data);
+        let header = reader.read::<ColorProfileHeader>()?;
+        Ok(match header.profile_type.get() {
+            0 => ColorProfile::None,
+            1 => ColorProfile::Srgb {
+                gamma: (header.flags.get() & FIXED_GAMMA != 0).then(|| Fixed::from_bits(header.gamma.get())),
+            },
+            2 => {
+                let len = reader.u32()?;
+                ColorProfile::Icc(reader.bytes(len as usize)?)
+            }
+            value => return Err(Error::bad_enum(0, "color profile type", value as u32)),
+        })
+    }
+
+    /// A converter from the pixel values of a fixed gamma profile to sRGB, or `None`
+    /// when the values can be shown as they are. ICC profiles are not interpreted.
+    pub fn srgb_converter(&self) -> Option<SrgbConverter> {
+        match *self {
+            ColorProfile::Srgb { gamma: Some(gamma) } => Some(SrgbConverter::new(gamma)),
+            _ => None,
+        }
+    }
+}
+
+/// Maps color channels stored with a fixed gamma to sRGB through a lookup table, so that
+/// converting a pixel doesn't need floating point.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SrgbConverter {
+    table: [u8; 256],
+}
+
+impl SrgbConverter {
+    /// Builds the table for values stored with `gamma`, where 1.0 means linear.
+    pub fn new(gamma: Fixed) -> Self {
+        let gamma = gamma.to_f32();
+        let mut table = [0; 256];
+        for (value, entry) in table.iter_mut().enumerate() {
+            let linear = libm::powf(value as f32 / 255.0, gamma);
+            let encoded = match linear <= 0.003_130_8 {
+                true => linear * 12.92,
+                false => 1.055 * libm::powf(linear, 1.0 / 2.4) - 0.055,
+            };
+            *entry = libm::roundf(encoded.clamp(0.0, 1.0) * 255.0) as u8;
+        }
+        SrgbConverter { table }
+    }
+
+    pub fn channel(&self, value: u8) -> u8 {
+        self.table[value as usize]
+    }
+
+    /// Converts the color channels of `color`, alpha is kept as is.
+    pub fn convert(&self, color: Rgba) -> Rgba {
+        Rgba::new(self.channel(color.r), self.channel(color.g), self.channel(color.b), color.a)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::error::ErrorKind;
+    use std::vec::Vec;
+
+    fn profile(profile_type: u16, flags: u16, gamma: i32) -> Vec<u8> {
+        [&profile_type.to_le_bytes()[..], &flags.to_le_bytes(), &gamma.to_le_bytes(), &[0; 8]].concat()
+    }
+
+    #[test]
+    fn profiles() {
+        assert_eq!(ColorProfile::new(&profile(0, 0, 0)).unwrap(), ColorProfile::None);
+        assert_eq!(ColorProfile::new(&profile(1, 0, 123)).unwrap(), ColorProfile::Srgb { gamma: None });
+        assert_eq!(ColorProfile::new(&profile(1, 1, 0x10000)).unwrap(), ColorProfile::Srgb { gamma: Some(Fixed::ONE) });
+
+        let icc = [&profile(2, 0, 0)[..], &3u32.to_le_bytes(), &[7, 8, 9]].concat();
+        assert_eq!(ColorProfile::new(&icc).unwrap(), ColorProfile::Icc(&[7, 8, 9]));
+        assert!(matches!(ColorProfile::new(&icc[..icc.len() - 1]).unwrap_err().kind, ErrorKind::Truncated { .. }));
+
+        let e = ColorProfile::new(&profile(3, 0, 0)).unwrap_err();
+        assert_eq!(e.kind, ErrorKind::BadEnumValue { field: "color profile type", value: 3 });
+    }
+
+    #[test]
+    fn linear_to_srgb() {
+        assert_eq!(ColorProfile::Srgb { gamma: None }.srgb_converter(), None);
+        let linear = ColorProfile::Srgb { gamma: Some(Fixed::ONE) }.srgb_converter().unwrap();
+        assert_eq!((linear.channel(0), linear.channel(255)), (0, 255));
+        // linear 0.5 is brighter once encoded
+        assert_eq!(linear.channel(128), 188);
+        assert_eq!(linear.convert(Rgba::new(0, 128, 255, 10)), Rgba::new(0, 188, 255, 10));
+
+        // gamma 2.2 is close to sRGB already
+        let gamma = SrgbConverter::new(Fixed::from_bits(0x0002_3333));
+        assert!(gamma.channel(128).abs_diff(128) <= 2);
+    }
+}
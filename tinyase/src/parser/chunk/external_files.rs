@@ -0,0 +1,164 @@
+use core::fmt::{self, Debug, Formatter};
+
+use crate::error::Error;
+use crate::parser::bytes::ByteReader;
+
+/// What an [`ExternalFile`] entry points to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExternalFileKind {
+    ExternalPalette,
+    ExternalTileset,
+    /// Extension name for the properties of user data
+    PropertiesExtension,
+    /// Extension name for tile management
+    TileManagementExtension,
+}
+
+impl TryFrom<u8> for ExternalFileKind {
+    type Error = Error;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        Ok(match value {
+            0 => ExternalFileKind::ExternalPalette,
+            1 => ExternalFileKind::ExternalTileset,
+            2 => ExternalFileKind::PropertiesExtension,
+            3 => ExternalFileKind::TileManagementExtension,
+            _ => return Err(Error::bad_enum(0, "external file type", value as u32)),
+        })
+    }
+}
+
+/// An entry of the [`ExternalFiles`] chunk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExternalFile<'a> {
+    /// ID that tilesets and properties use to refer to this entry
+    pub id: u32,
+    pub kind: ExternalFileKind,
+    /// File name, or extension ID for extensions
+    pub name: &'a str,
+}
+
+/// External files chunk (0x2008).
+#[derive(Clone)]
+pub struct ExternalFiles<'a> {
+    count: u32,
+    data: &'a [u8],
+}
+
+impl<'a> ExternalFiles<'a> {
+    /// Checks every entry.
+    pub fn new(data: &'a [u8]) -> Result<Self, Error> {
+        let mut reader = ByteReader::new(data);
+        let count = reader.u32()?;
+        reader.bytes(8)?;
+        let entries = reader.remaining();
+        for _ in 0..count {
+            read_entry(&mut reader)?;
+        }
+        Ok(ExternalFiles { count, data: &entries[..reader.position() - 12] })
+    }
+
+    /// Number of entries
+    pub fn len(&self) -> u32 {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    pub fn iter(&self) -> ExternalFileIterator<'a> {
+        ExternalFileIterator { reader: ByteReader::new(self.data), remaining: self.count }
+    }
+
+    /// Finds an entry by its ID.
+    pub fn get(&self, id: u32) -> Option<ExternalFile<'a>> {
+        self.iter().find(|file| file.id == id)
+    }
+}
+
+impl Debug for ExternalFiles<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<'a> IntoIterator for &ExternalFiles<'a> {
+    type Item = ExternalFile<'a>;
+    type IntoIter = ExternalFileIterator<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+fn read_entry<'a>(reader: &mut ByteReader<'a>) -> Result<ExternalFile<'a>, Error> {
+    let id = reader.u32()?;
+    let start = reader.position();
+    let kind = ExternalFileKind::try_from(reader.u8()?).map_err(|e| e.offset_by(start))?;
+    reader.bytes(7)?;
+    Ok(ExternalFile { id, kind, name: reader.string()? })
+}
+
+/// Iterator over the entries of an [`ExternalFiles`] chunk.
+#[derive(Debug, Clone)]
+pub struct ExternalFileIterator<'a> {
+    reader: ByteReader<'a>,
+    remaining: u32,
+}
+
+impl<'a> Iterator for ExternalFileIterator<'a> {
+    type Item = ExternalFile<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None
+        }
+        self.remaining -= 1;
+        read_entry(&mut self.reader).ok()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining as usize, Some(self.remaining as usize))
+    }
+}
+
+/// Hands out the contents of the files an aseprite file refers to, such as a tileset
+/// shared by several levels.
+///
+/// The crate never touches a file system, so users implement this for wherever their
+/// assets live. Closures taking an [`ExternalFile`] implement it too.
+pub trait ExternalResolver<'a> {
+    /// The whole contents of the `.ase` file for `file`, or `None` if it isn't available.
+    fn resolve(&mut self, file: &ExternalFile<'_>) -> Option<&'a [u8]>;
+}
+
+impl<'a, F> ExternalResolver<'a> for F
+where
+    F: FnMut(&ExternalFile<'_>) -> Option<&'a [u8]>,
+{
+    fn resolve(&mut self, file: &ExternalFile<'_>) -> Option<&'a [u8]> {
+        self(file)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::error::ErrorKind;
+    use crate::test_util::external_files;
+
+    #[test]
+    fn entries() {
+        let data = external_files(&[(4, 1, "shared.aseprite"), (9, 2, "ext.props")]);
+        let files = ExternalFiles::new(&data).unwrap();
+        assert_eq!(files.len(), 2);
+        assert_eq!(files.get(4), Some(ExternalFile { id: 4, kind: ExternalFileKind::ExternalTileset, name: "shared.aseprite" }));
+        assert_eq!(files.iter().map(|f| f.kind).last(), Some(ExternalFileKind::PropertiesExtension));
+        assert_eq!(files.get(5), None);
+
+        let bad = external_files(&[(1, 4, "x")]);
+        let e = ExternalFiles::new(&bad).unwrap_err();
+        assert_eq!((e.kind, e.offset), (ErrorKind::BadEnumValue { field: "external file type", value: 4 }, 16));
+    }
+}
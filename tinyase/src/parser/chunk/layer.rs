@@ -1,18 +1,26 @@
-use core::{fmt::{Debug, Formatter, Result}, marker::PhantomData, ops::Deref};
+use core::fmt::{self, Debug, Formatter};
 
-use thiserror::Error;
 use zerocopy::*;
 use bitflags::bitflags;
 
+use crate::error::Error;
+use crate::parser::HeaderFlags;
+use crate::parser::bytes::ByteReader;
+use crate::parser::chunk::user_data::UserData;
+
 #[derive(Debug)]
 pub struct Layer<'a> {
     pub header: &'a LayerHeader,
     pub name: &'a str,
-    rest: &'a [u8],
+    layer_type: LayerType,
+    blend_mode: BlendMode,
+    tileset_index: Option<u32>,
+    uuid: Option<&'a [u8; 16]>,
+    pub(crate) user_data: Option<UserData<'a>>,
 }
 
 bitflags! {
-    #[derive(Debug)]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
     pub struct LayerFlag : u16 {
         const VISIBLE = 0x1;
         const EDITABLE = 0x2;
@@ -34,14 +42,14 @@ impl From<&LayerFlags> for LayerFlag {
 }
 
 impl Debug for LayerFlags {
-    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         let bits: LayerFlag = self.into();
         write!(f, "{:?}", bits)
     }
 }
 
 #[repr(u16)]
-#[derive(Debug, TryFromBytes, KnownLayout, Immutable)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, TryFromBytes, KnownLayout, Immutable)]
 pub enum LayerType {
     Normal = 0,
     Group = 1,
@@ -49,7 +57,7 @@ pub enum LayerType {
 }
 
 #[repr(u16)]
-#[derive(Debug, Clone, Copy, TryFromBytes, KnownLayout, Immutable)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, TryFromBytes, KnownLayout, Immutable)]
 pub enum BlendMode {
     Normal = 0,
     Multiply = 1,
@@ -72,30 +80,56 @@ pub enum BlendMode {
     Divide = 18,
 }
 
-#[derive(TryFromBytes, KnownLayout, Immutable, Unaligned)]  
+#[derive(FromBytes, KnownLayout, Immutable, Unaligned)]  
 #[repr(transparent)]  
 struct RawBlend([u8; 2]);
 
-// TODO: use try into
-impl From<&RawBlend> for BlendMode {
-    fn from(value: &RawBlend) -> Self {
-        Self::try_read_from_bytes(&value.0).unwrap()
+impl TryFrom<&RawBlend> for BlendMode {
+    type Error = Error;
+
+    fn try_from(value: &RawBlend) -> Result<Self, Self::Error> {
+        Self::try_read_from_bytes(&value.0)
+            .map_err(|_| Error::bad_enum(0, "blend mode", u16::from_le_bytes(value.0) as u32))
+    }
+}
+
+#[derive(FromBytes, KnownLayout, Immutable, Unaligned)]
+#[repr(transparent)]
+struct RawLayerType([u8; 2]);
+
+impl TryFrom<&RawLayerType> for LayerType {
+    type Error = Error;
+
+    fn try_from(value: &RawLayerType) -> Result<Self, Self::Error> {
+        Self::try_read_from_bytes(&value.0)
+            .map_err(|_| Error::bad_enum(0, "layer type", u16::from_le_bytes(value.0) as u32))
+    }
+}
+
+impl Debug for RawLayerType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match LayerType::try_from(self) {
+            Ok(layer_type) => write!(f, "{:?}", layer_type),
+            Err(_) => write!(f, "Invalid({})", u16::from_le_bytes(self.0)),
+        }
     }
 }
 
 impl Debug for RawBlend {
-    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
-        let blend_mode: BlendMode = self.into();
-        write!(f, "{:?}", blend_mode)
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match BlendMode::try_from(self) {
+            Ok(blend_mode) => write!(f, "{:?}", blend_mode),
+            Err(_) => write!(f, "Invalid({})", u16::from_le_bytes(self.0)),
+        }
     }
 }
 
 
-#[derive(Debug, TryFromBytes, KnownLayout, Immutable, Unaligned)]
+#[derive(Debug, FromBytes, KnownLayout, Immutable, Unaligned)]
 #[repr(C)]
 pub struct LayerHeader {
     flags: LayerFlags,
-    layer_type: U16<LE>,
+    layer_type: RawLayerType,
     child_level: U16<LE>,
     _ignored_width: U16<LE>,
     _ignored_height: U16<LE>,
@@ -104,13 +138,85 @@ pub struct LayerHeader {
     _reserved: [u8; 3],
 }
 
+impl LayerHeader {
+    pub fn flags(&self) -> LayerFlag {
+        (&self.flags).into()
+    }
+
+    pub fn layer_type(&self) -> Result<LayerType, Error> {
+        LayerType::try_from(&self.layer_type).map_err(|e| e.offset_by(2))
+    }
+
+    /// Nesting level of the layer, children follow their group with a level one higher
+    pub fn child_level(&self) -> u16 {
+        self.child_level.get()
+    }
+
+    /// Only valid when the file header has
+    /// [`HeaderFlags::LAYER_OPACITY_VALID`](crate::parser::HeaderFlags::LAYER_OPACITY_VALID)
+    pub fn blend_mode(&self) -> Result<BlendMode, Error> {
+        BlendMode::try_from(&self.blend_mode).map_err(|e| e.offset_by(10))
+    }
+
+    /// Only valid when the file header has
+    /// [`HeaderFlags::LAYER_OPACITY_VALID`](crate::parser::HeaderFlags::LAYER_OPACITY_VALID)
+    pub fn opacity(&self) -> u8 {
+        self.opacity
+    }
+}
+
 impl<'a> Layer<'a> {
-    pub fn new(data: &'a [u8]) -> Self {
-        let (layer, rest) = LayerHeader::try_ref_from_prefix(data).unwrap();
-        let (name_len, rest) = U16::<LE>::ref_from_prefix(rest).unwrap();
-        let name_len: usize = name_len.get() as _;
-        let layer_name = str::from_utf8(&rest[..name_len]).unwrap();
-        Layer { header: layer, name: layer_name, rest: &rest[name_len..] }
+    /// `flags` are the flags of the file header, which tell whether layers carry a UUID.
+    pub fn new(data: &'a [u8], flags: HeaderFlags) -> Result<Self, Error> {
+        let mut reader = ByteReader::new(data);
+        let header = reader.read::<LayerHeader>()?;
+        let layer_type = header.layer_type()?;
+        let blend_mode = header.blend_mode()?;
+        let name = reader.string()?;
+        let tileset_index = match layer_type {
+            LayerType::Tilemap => Some(reader.u32()?),
+            _ => None,
+        };
+        let uuid = match flags.contains(HeaderFlags::LAYERS_HAVE_UUID) {
+            true => Some(reader.read::<[u8; 16]>()?),
+            false => None,
+        };
+        Ok(Layer { header, name, layer_type, blend_mode, tileset_index, uuid, user_data: None })
+    }
+
+    pub fn flags(&self) -> LayerFlag {
+        self.header.flags()
+    }
+
+    pub fn layer_type(&self) -> LayerType {
+        self.layer_type
+    }
+
+    pub fn child_level(&self) -> u16 {
+        self.header.child_level()
+    }
+
+    pub fn blend_mode(&self) -> BlendMode {
+        self.blend_mode
+    }
+
+    pub fn opacity(&self) -> u8 {
+        self.header.opacity()
+    }
+
+    /// Index of the tileset used by a tilemap layer
+    pub fn tileset_index(&self) -> Option<u32> {
+        self.tileset_index
+    }
+
+    /// UUID of the layer, when the file header has [`HeaderFlags::LAYERS_HAVE_UUID`]
+    pub fn uuid(&self) -> Option<[u8; 16]> {
+        self.uuid.copied()
+    }
+
+    /// User data of this layer, see [`user_data`](super::user_data).
+    pub fn user_data(&self) -> Option<&UserData<'a>> {
+        self.user_data.as_ref()
     }
 }
 
@@ -126,16 +232,78 @@ mod test {
 
         let r = HeaderReader::new(data);
         let mut frames = r.frames();
-        let frame_1 = frames.next().unwrap();
+        let frame_1 = frames.next().unwrap().unwrap();
 
-        for chunk in frame_1.chunks() {
-            println!("{:?}", chunk);
-        }
-        let ASEChunk::Layer(layer) = frame_1.chunks().find(|c| 
-            matches!(c, ASEChunk::Layer(_))
+        let names: Vec<&str> = frame_1.chunks().filter_map(|c| match c.unwrap() {
+            ASEChunk::Layer(layer) => Some(layer.name),
+            _ => None,
+        }).collect();
+        assert_eq!(names, ["Background", "floor", "plant", "chair", "Mouse", "chair_fg", "desk"]);
+        let Ok(ASEChunk::Layer(layer)) = frame_1.chunks().find(|c| 
+            matches!(c, Ok(ASEChunk::Layer(_)))
         ).unwrap() else {panic!("No layer chunk found")};
 
-        let bl: BlendMode = (&layer.header.blend_mode).into();
-        println!("{:?}", bl);
+        assert!(layer.flags().contains(LayerFlag::BACKGROUND));
+        let bl: BlendMode = (&layer.header.blend_mode).try_into().unwrap();
+        assert_eq!(bl, BlendMode::Normal);
+    }
+
+    #[test]
+    fn typed_accessors() {
+        let v = std::fs::read("tests/anim_idle.ase").unwrap();
+        let r = HeaderReader::new(&v);
+        let frame_1 = r.frames().next().unwrap().unwrap();
+        let Some(Ok(ASEChunk::Layer(layer))) = frame_1.chunks().find(|c| matches!(c, Ok(ASEChunk::Layer(_)))) else {
+            panic!("No layer chunk found")
+        };
+        assert_eq!(layer.blend_mode(), BlendMode::Normal);
+        assert_eq!(layer.tileset_index(), None);
+        assert_eq!(layer.uuid().is_some(), r.header().flags().contains(HeaderFlags::LAYERS_HAVE_UUID));
+    }
+
+    #[test]
+    fn tileset_index_and_uuid() {
+        let mut data = std::vec![0u8; 16 + 2 + 1 + 4 + 16];
+        data[0] = 0x03;
+        data[2] = 2;
+        data[4] = 1;
+        data[10] = 16;
+        data[12] = 128;
+        data[16] = 1;
+        data[18] = b't';
+        data[19..23].copy_from_slice(&7u32.to_le_bytes());
+        data[23..].copy_from_slice(&[0xAB; 16]);
+
+        let layer = Layer::new(&data, HeaderFlags::LAYERS_HAVE_UUID).unwrap();
+        assert_eq!(layer.flags(), LayerFlag::VISIBLE | LayerFlag::EDITABLE);
+        assert_eq!((layer.layer_type(), layer.child_level()), (LayerType::Tilemap, 1));
+        assert_eq!((layer.blend_mode(), layer.opacity()), (BlendMode::Addition, 128));
+        assert_eq!(layer.tileset_index(), Some(7));
+        assert_eq!(layer.uuid(), Some([0xAB; 16]));
+
+        let layer = Layer::new(&data[..23], HeaderFlags::empty()).unwrap();
+        assert_eq!((layer.tileset_index(), layer.uuid()), (Some(7), None));
+        let e = Layer::new(&data[..38], HeaderFlags::LAYERS_HAVE_UUID).unwrap_err();
+        assert_eq!(e.kind, crate::ErrorKind::Truncated { needed: 16, available: 15 });
+
+        data[2] = 3;
+        let e = Layer::new(&data, HeaderFlags::LAYERS_HAVE_UUID).unwrap_err();
+        assert_eq!((e.kind, e.offset), (crate::ErrorKind::BadEnumValue { field: "layer type", value: 3 }, 2));
+    }
+
+    #[test]
+    fn invalid_layer_is_an_error() {
+        let mut data = [0u8; 16 + 2 + 3];
+        data[16] = 3;
+        data[18..].copy_from_slice(b"abc");
+        assert_eq!(Layer::new(&data, HeaderFlags::empty()).unwrap().name, "abc");
+
+        data[10] = 19;
+        assert_eq!(Layer::new(&data, HeaderFlags::empty()).unwrap_err().offset, 10);
+        data[10] = 0;
+
+        data[18] = 0xFF;
+        assert_eq!(Layer::new(&data, HeaderFlags::empty()).unwrap_err().kind, crate::ErrorKind::InvalidUtf8);
+        assert!(matches!(Layer::new(&data[..19], HeaderFlags::empty()).unwrap_err().kind, crate::ErrorKind::Truncated { needed: 3, available: 1 }));
     }
 }
\ No newline at end of file
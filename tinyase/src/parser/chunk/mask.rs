@@ -0,0 +1,137 @@
+use zerocopy::*;
+
+use crate::error::Error;
+use crate::parser::bytes::ByteReader;
+
+#[derive(Debug, FromBytes, KnownLayout, Immutable, Unaligned)]
+#[repr(C)]
+struct MaskHeader {
+    x: I16<LE>,
+    y: I16<LE>,
+    width: U16<LE>,
+    height: U16<LE>,
+    _reserved: [u8; 8],
+}
+
+/// Mask chunk (0x2016), deprecated. A 1 bit per pixel bitmap placed in the sprite.
+#[derive(Debug, Clone)]
+pub struct Mask<'a> {
+    pub x: i16,
+    pub y: i16,
+    pub width: u16,
+    pub height: u16,
+    pub name: &'a str,
+    bitmap: &'a [u8],
+}
+
+impl<'a> Mask<'a> {
+    /// Checks that the bitmap is complete.
+    pub fn new(data: &'a [u8]) -> Result<Self, Error> {
+        let mut reader = ByteReader::new(data);
+        let header = reader.read::<MaskHeader>()?;
+        let name = reader.string()?;
+        let (width, height) = (header.width.get(), header.height.get());
+        let bitmap = reader.bytes(height as usize * row_len(width))?;
+        Ok(Mask { x: header.x.get(), y: header.y.get(), width, height, name, bitmap })
+    }
+
+    /// Whether the pixel at `(x, y)` of the sprite is inside the mask.
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        let (x, y) = (x - self.x as i32, y - self.y as i32);
+        if x < 0 || y < 0 || x >= self.width as i32 || y >= self.height as i32 {
+            return false;
+        }
+        self.get(x as usize, y as usize)
+    }
+
+    /// The bits of the mask in rows from its top left corner, `true` inside the mask.
+    pub fn pixels(&self) -> MaskPixels<'a> {
+        MaskPixels { bitmap: self.bitmap, width: self.width, x: 0, y: 0, height: self.height }
+    }
+
+    fn get(&self, x: usize, y: usize) -> bool {
+        bit(self.bitmap, row_len(self.width), x, y)
+    }
+}
+
+/// Bytes per bitmap row, rows are padded to a whole byte.
+fn row_len(width: u16) -> usize {
+    (width as usize).div_ceil(8)
+}
+
+fn bit(bitmap: &[u8], row_len: usize, x: usize, y: usize) -> bool {
+    bitmap.get(y * row_len + x / 8).is_some_and(|b| b & (0x80 >> (x % 8)) != 0)
+}
+
+/// Iterator over the pixels of a [`Mask`].
+#[derive(Debug, Clone)]
+pub struct MaskPixels<'a> {
+    bitmap: &'a [u8],
+    width: u16,
+    height: u16,
+    x: u16,
+    y: u16,
+}
+
+impl Iterator for MaskPixels<'_> {
+    type Item = bool;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.width == 0 || self.y >= self.height {
+            return None;
+        }
+        let inside = bit(self.bitmap, row_len(self.width), self.x as usize, self.y as usize);
+        self.x += 1;
+        if self.x == self.width {
+            self.x = 0;
+            self.y += 1;
+        }
+        Some(inside)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let total = self.width as usize * self.height as usize;
+        let len = total.saturating_sub(self.y as usize * self.width as usize + self.x as usize);
+        (len, Some(len))
+    }
+}
+
+impl ExactSizeIterator for MaskPixels<'_> {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::error::ErrorKind;
+    use crate::test_util::string;
+    use std::vec::Vec;
+
+    fn mask(x: i16, y: i16, width: u16, height: u16, bitmap: &[u8]) -> Vec<u8> {
+        [
+            &x.to_le_bytes()[..], &y.to_le_bytes(), &width.to_le_bytes(), &height.to_le_bytes(),
+            &[0; 8], &string("hit"), bitmap,
+        ].concat()
+    }
+
+    #[test]
+    fn bitmap() {
+        // 10x2, rows padded to 2 bytes
+        let data = mask(-2, 3, 10, 2, &[0b1000_0001, 0b0100_0000, 0b0000_0000, 0b1000_0000]);
+        let m = Mask::new(&data).unwrap();
+        assert_eq!((m.x, m.y, m.width, m.height, m.name), (-2, 3, 10, 2, "hit"));
+        assert!(m.contains(-2, 3));
+        assert!(!m.contains(-1, 3));
+        assert!(m.contains(5, 3));
+        assert!(m.contains(7, 3));
+        assert!(m.contains(6, 4));
+        assert!(!m.contains(8, 4));
+        assert!(!m.contains(-3, 3));
+
+        let pixels: Vec<bool> = m.pixels().collect();
+        assert_eq!(m.pixels().len(), 20);
+        let inside: Vec<usize> = (0..20).filter(|&i| pixels[i]).collect();
+        assert_eq!(inside, [0, 7, 9, 18]);
+
+        let e = Mask::new(&data[..data.len() - 1]).unwrap_err();
+        assert_eq!(e.kind, ErrorKind::Truncated { needed: 4, available: 3 });
+    }
+}
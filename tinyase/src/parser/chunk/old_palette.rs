@@ -0,0 +1,138 @@
+use crate::error::Error;
+use crate::parser::bytes::ByteReader;
+use crate::parser::chunk::palette::Rgba;
+
+/// Old palette chunk, 0x0004 with 8-bit channels or 0x0011 with 6-bit channels.
+///
+/// Written by Aseprite before v1.1 and by FLI based tools. Both are ignored by
+/// [`HeaderReader::palette_for_frame`](crate::parser::HeaderReader::palette_for_frame)
+/// when the file has a new palette chunk (0x2019).
+#[derive(Debug, Clone)]
+pub struct OldPalette<'a> {
+    six_bit: bool,
+    packets: u16,
+    data: &'a [u8],
+}
+
+impl<'a> OldPalette<'a> {
+    /// Checks that every packet is complete.
+    pub fn new(chunk_type: u16, data: &'a [u8]) -> Result<Self, Error> {
+        let mut reader = ByteReader::new(data);
+        let packets = reader.u16()?;
+        for _ in 0..packets {
+            let [_skip, count] = reader.read::<[u8; 2]>()?;
+            reader.bytes(packet_len(*count) * 3)?;
+        }
+        Ok(OldPalette { six_bit: chunk_type == 0x0011, packets, data: &data[2..reader.position()] })
+    }
+
+    /// Whether the channels are stored as 0-63, as in chunk type 0x0011.
+    /// [`OldPalette::entries`] scales them to 0-255.
+    pub fn is_six_bit(&self) -> bool {
+        self.six_bit
+    }
+
+    pub fn packets(&self) -> u16 {
+        self.packets
+    }
+
+    /// Iterates over `(index, color)` of the entries changed by this chunk, all opaque.
+    pub fn entries(&self) -> OldPaletteEntries<'a> {
+        OldPaletteEntries {
+            reader: ByteReader::new(self.data),
+            six_bit: self.six_bit,
+            packets: self.packets,
+            index: 0,
+            left_in_packet: 0,
+        }
+    }
+}
+
+fn packet_len(count: u8) -> usize {
+    match count {
+        0 => 256,
+        count => count as usize,
+    }
+}
+
+/// Scales a 6-bit channel to 8 bits so that 63 becomes 255.
+fn scale_six_bit(v: u8) -> u8 {
+    let v = v & 0x3F;
+    (v << 2) | (v >> 4)
+}
+
+/// Iterator over the entries of an [`OldPalette`] chunk.
+#[derive(Debug, Clone)]
+pub struct OldPaletteEntries<'a> {
+    reader: ByteReader<'a>,
+    six_bit: bool,
+    packets: u16,
+    index: u32,
+    left_in_packet: usize,
+}
+
+impl Iterator for OldPaletteEntries<'_> {
+    type Item = (u32, Rgba);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.left_in_packet == 0 {
+            if self.packets == 0 {
+                return None
+            }
+            self.packets -= 1;
+            let [skip, count] = *self.reader.read::<[u8; 2]>().ok()?;
+            self.index += skip as u32;
+            self.left_in_packet = packet_len(count);
+        }
+        let &[r, g, b] = self.reader.read::<[u8; 3]>().ok()?;
+        let color = match self.six_bit {
+            true => Rgba::new(scale_six_bit(r), scale_six_bit(g), scale_six_bit(b), 255),
+            false => Rgba::new(r, g, b, 255),
+        };
+        let index = self.index;
+        self.index += 1;
+        self.left_in_packet -= 1;
+        Some((index, color))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::error::ErrorKind;
+    use std::vec::Vec;
+
+    #[test]
+    fn old_palette_packets() {
+        // two packets: entries 0-1, then skip 3 and set entry 5
+        let data = [2, 0, 0, 2, 1, 2, 3, 4, 5, 6, 3, 1, 7, 8, 9];
+        let p = OldPalette::new(0x0004, &data).unwrap();
+        assert!(!p.is_six_bit());
+        let entries: Vec<_> = p.entries().collect();
+        assert_eq!(entries, [
+            (0, Rgba::new(1, 2, 3, 255)),
+            (1, Rgba::new(4, 5, 6, 255)),
+            (5, Rgba::new(7, 8, 9, 255)),
+        ]);
+    }
+
+    #[test]
+    fn six_bit_palette_is_scaled() {
+        let data = [1, 0, 0, 1, 63, 32, 0];
+        let p = OldPalette::new(0x0011, &data).unwrap();
+        assert!(p.is_six_bit());
+        let entries: Vec<_> = p.entries().collect();
+        assert_eq!(entries, [(0, Rgba::new(255, 130, 0, 255))]);
+    }
+
+    #[test]
+    fn full_packet_and_truncation() {
+        let mut data = std::vec![1, 0, 0, 0];
+        data.extend_from_slice(&[0; 256 * 3]);
+        assert_eq!(OldPalette::new(0x0004, &data).unwrap().entries().count(), 256);
+
+        let e = OldPalette::new(0x0004, &data[..100]).unwrap_err();
+        assert_eq!(e.kind, ErrorKind::Truncated { needed: 768, available: 96 });
+        assert_eq!(e.offset, 4);
+    }
+}
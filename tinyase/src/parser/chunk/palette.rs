@@ -0,0 +1,167 @@
+use zerocopy::*;
+
+use crate::error::{Error, ErrorKind};
+use crate::parser::bytes::ByteReader;
+
+/// An 8-bit per channel, non-premultiplied color.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, FromBytes, KnownLayout, Immutable, Unaligned)]
+#[repr(C)]
+pub struct Rgba {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: u8,
+}
+
+impl Rgba {
+    pub const TRANSPARENT: Rgba = Rgba::new(0, 0, 0, 0);
+
+    pub const fn new(r: u8, g: u8, b: u8, a: u8) -> Self {
+        Rgba { r, g, b, a }
+    }
+}
+
+/// Header of the palette chunk (0x2019).
+#[derive(Debug, FromBytes, KnownLayout, Immutable, Unaligned)]
+#[repr(C)]
+pub struct PaletteHeader {
+    size: U32<LE>,
+    first: U32<LE>,
+    last: U32<LE>,
+    _reserved: [u8; 8],
+}
+
+impl PaletteHeader {
+    /// Total number of entries of the palette after this chunk is applied
+    pub fn size(&self) -> u32 {
+        self.size.get()
+    }
+
+    /// Index of the first entry changed by this chunk
+    pub fn first(&self) -> u32 {
+        self.first.get()
+    }
+
+    /// Index of the last entry changed by this chunk, inclusive
+    pub fn last(&self) -> u32 {
+        self.last.get()
+    }
+}
+
+#[derive(Debug, FromBytes, KnownLayout, Immutable, Unaligned)]
+#[repr(C)]
+struct EntryHeader {
+    flags: U16<LE>,
+    color: Rgba,
+}
+
+const ENTRY_HAS_NAME: u16 = 1;
+
+/// Palette chunk (0x2019), changing the entries `first..=last` of the sprite palette.
+#[derive(Debug, Clone)]
+pub struct Palette<'a> {
+    pub header: &'a PaletteHeader,
+    entries: &'a [u8],
+}
+
+impl<'a> Palette<'a> {
+    /// Checks that every entry is complete.
+    pub fn new(data: &'a [u8]) -> Result<Self, Error> {
+        let mut reader = ByteReader::new(data);
+        let header = reader.read::<PaletteHeader>()?;
+        let (first, last) = (header.first(), header.last());
+        if first > last {
+            return Err(Error::new(ErrorKind::InvalidPaletteRange { first, last }, 4));
+        }
+        let entries = reader.remaining();
+        // Each entry takes at least 6 bytes, so this ends at the end of the data at the latest
+        for _ in first..=last {
+            read_entry(&mut reader)?;
+        }
+        Ok(Palette { header, entries: &entries[..reader.position() - size_of::<PaletteHeader>()] })
+    }
+
+    /// Iterates over `(index, color, name)` of the entries changed by this chunk.
+    pub fn entries(&self) -> PaletteEntries<'a> {
+        PaletteEntries {
+            reader: ByteReader::new(self.entries),
+            index: self.header.first(),
+            last: self.header.last(),
+        }
+    }
+}
+
+fn read_entry<'a>(reader: &mut ByteReader<'a>) -> Result<(Rgba, Option<&'a str>), Error> {
+    let entry = reader.read::<EntryHeader>()?;
+    let name = match entry.flags.get() & ENTRY_HAS_NAME {
+        0 => None,
+        _ => Some(reader.string()?),
+    };
+    Ok((entry.color, name))
+}
+
+/// Iterator over the entries of a [`Palette`] chunk.
+#[derive(Debug, Clone)]
+pub struct PaletteEntries<'a> {
+    reader: ByteReader<'a>,
+    index: u32,
+    last: u32,
+}
+
+impl<'a> Iterator for PaletteEntries<'a> {
+    type Item = (u32, Rgba, Option<&'a str>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.index > self.last {
+            return None
+        }
+        let (color, name) = read_entry(&mut self.reader).ok()?;
+        let index = self.index;
+        // last can be u32::MAX, so step past it without overflowing
+        match self.index.checked_add(1) {
+            Some(next) => self.index = next,
+            None => self.last = 0,
+        }
+        Some((index, color, name))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::{palette, string};
+
+    #[test]
+    fn palette_entries() {
+        let entries = [
+            &[0, 0, 1, 2, 3, 255][..],
+            &[1, 0, 10, 20, 30, 128],
+            &string("sky"),
+        ].concat();
+        let data = palette(8, 4, 5, &entries);
+        let p = Palette::new(&data).unwrap();
+        assert_eq!((p.header.size(), p.header.first(), p.header.last()), (8, 4, 5));
+
+        let entries: std::vec::Vec<_> = p.entries().collect();
+        assert_eq!(entries, [
+            (4, Rgba::new(1, 2, 3, 255), None),
+            (5, Rgba::new(10, 20, 30, 128), Some("sky")),
+        ]);
+    }
+
+    #[test]
+    fn invalid_palettes() {
+        let data = palette(8, 5, 4, &[]);
+        assert_eq!(Palette::new(&data).unwrap_err().kind, ErrorKind::InvalidPaletteRange { first: 5, last: 4 });
+
+        // second entry is missing
+        let data = palette(8, 0, 1, &[0, 0, 1, 2, 3, 255]);
+        let e = Palette::new(&data).unwrap_err();
+        assert_eq!(e.kind, ErrorKind::Truncated { needed: 6, available: 0 });
+        assert_eq!(e.offset, 26);
+
+        // a huge range can't make decoding loop for long
+        let data = palette(u32::MAX, 0, u32::MAX, &[0; 60]);
+        assert!(Palette::new(&data).is_err());
+    }
+}
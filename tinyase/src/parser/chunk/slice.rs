@@ -0,0 +1,222 @@
+use bitflags::bitflags;
+use zerocopy::*;
+
+use crate::error::Error;
+use crate::parser::bytes::ByteReader;
+use crate::parser::chunk::user_data::UserData;
+use crate::parser::types::{Point, Rect, Size};
+
+bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct SliceFlags : u32 {
+        const NINE_PATCH = 1;
+        const HAS_PIVOT = 2;
+    }
+}
+
+#[derive(Debug, FromBytes, KnownLayout, Immutable, Unaligned)]
+#[repr(C)]
+struct SliceHeader {
+    keys: U32<LE>,
+    flags: U32<LE>,
+    _reserved: U32<LE>,
+}
+
+#[derive(Debug, FromBytes, KnownLayout, Immutable, Unaligned)]
+#[repr(C)]
+struct RawRect {
+    x: I32<LE>,
+    y: I32<LE>,
+    width: U32<LE>,
+    height: U32<LE>,
+}
+
+/// Reads a rectangle, whose size must fit in the `i32` of [`Size`].
+fn read_rect(reader: &mut ByteReader<'_>) -> Result<Rect, Error> {
+    let start = reader.position();
+    let r = reader.read::<RawRect>()?;
+    let size = |value: u32, field, at| i32::try_from(value).map_err(|_| Error::bad_enum(start + at, field, value));
+    Ok(Rect {
+        origin: Point { x: r.x.get(), y: r.y.get() },
+        size: Size { width: size(r.width.get(), "slice width", 8)?, height: size(r.height.get(), "slice height", 12)? },
+    })
+}
+
+/// The state of a slice from `frame` until the frame of the next key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SliceKey {
+    pub frame: u32,
+    /// Bounds in the sprite, an empty size means the slice is hidden
+    pub bounds: Rect,
+    /// Center of a 9-patch slice, relative to `bounds`
+    pub center: Option<Rect>,
+    /// Pivot point, relative to the origin of `bounds`
+    pub pivot: Option<Point>,
+}
+
+/// Slice chunk (0x2022), a named region with one key per frame where it changes.
+#[derive(Debug, Clone)]
+pub struct Slice<'a> {
+    pub name: &'a str,
+    flags: SliceFlags,
+    count: u32,
+    keys: &'a [u8],
+    pub(crate) user_data: Option<UserData<'a>>,
+}
+
+impl<'a> Slice<'a> {
+    /// Checks that every key is complete and that sizes fit in an `i32`.
+    pub fn new(data: &'a [u8]) -> Result<Self, Error> {
+        let mut reader = ByteReader::new(data);
+        let header = reader.read::<SliceHeader>()?;
+        let flags = SliceFlags::from_bits_truncate(header.flags.get());
+        let name = reader.string()?;
+        let count = header.keys.get();
+        let position = reader.position();
+        let len = (count as usize).checked_mul(key_len(flags))
+            .ok_or(Error::truncated(position, usize::MAX, reader.remaining().len()))?;
+        let keys = reader.bytes(len)?;
+        let mut keys_reader = ByteReader::new(keys);
+        for _ in 0..count {
+            read_key(&mut keys_reader, flags).map_err(|e| e.offset_by(position))?;
+        }
+        Ok(Slice { name, flags, count, keys, user_data: None })
+    }
+
+    pub fn flags(&self) -> SliceFlags {
+        self.flags
+    }
+
+    /// Number of keys
+    pub fn len(&self) -> u32 {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    pub fn keys(&self) -> SliceKeys<'a> {
+        SliceKeys { data: self.keys, flags: self.flags }
+    }
+
+    /// The key in effect at `frame`, the last key starting at or before it.
+    pub fn key_for_frame(&self, frame: u32) -> Option<SliceKey> {
+        self.keys().filter(|key| key.frame <= frame).max_by_key(|key| key.frame)
+    }
+
+    /// User data of this slice, see [`user_data`](super::user_data).
+    pub fn user_data(&self) -> Option<&UserData<'a>> {
+        self.user_data.as_ref()
+    }
+}
+
+fn read_key(reader: &mut ByteReader<'_>, flags: SliceFlags) -> Result<SliceKey, Error> {
+    let frame = reader.u32()?;
+    let bounds = read_rect(reader)?;
+    let center = match flags.contains(SliceFlags::NINE_PATCH) {
+        true => Some(read_rect(reader)?),
+        false => None,
+    };
+    let pivot = match flags.contains(SliceFlags::HAS_PIVOT) {
+        true => Some(Point { x: reader.i32()?, y: reader.i32()? }),
+        false => None,
+    };
+    Ok(SliceKey { frame, bounds, center, pivot })
+}
+
+fn key_len(flags: SliceFlags) -> usize {
+    let mut len = 4 + size_of::<RawRect>();
+    if flags.contains(SliceFlags::NINE_PATCH) {
+        len += size_of::<RawRect>();
+    }
+    if flags.contains(SliceFlags::HAS_PIVOT) {
+        len += 8;
+    }
+    len
+}
+
+/// Iterator over the keys of a [`Slice`].
+#[derive(Debug, Clone)]
+pub struct SliceKeys<'a> {
+    data: &'a [u8],
+    flags: SliceFlags,
+}
+
+impl Iterator for SliceKeys<'_> {
+    type Item = SliceKey;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (key, rest) = self.data.split_at_checked(key_len(self.flags))?;
+        self.data = rest;
+        read_key(&mut ByteReader::new(key), self.flags).ok()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.data.len() / key_len(self.flags);
+        (len, Some(len))
+    }
+}
+
+impl ExactSizeIterator for SliceKeys<'_> {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::error::ErrorKind;
+    use crate::test_util::string;
+    use std::vec::Vec;
+
+    fn longs(values: &[i32]) -> Vec<u8> {
+        values.iter().flat_map(|v| v.to_le_bytes()).collect()
+    }
+
+    fn rect(x: i32, y: i32, width: i32, height: i32) -> Rect {
+        Rect { origin: Point { x, y }, size: Size { width, height } }
+    }
+
+    #[test]
+    fn slice_keys() {
+        let data = [
+            longs(&[2, 3, 0]),
+            string("hitbox"),
+            longs(&[0, 1, 2, 10, 20]),
+            longs(&[0, 0, 4, 4]),
+            longs(&[5, 6]),
+            longs(&[4, -1, -2, 0, 0]),
+            longs(&[1, 1, 2, 2]),
+            longs(&[7, 8]),
+        ].concat();
+        let slice = Slice::new(&data).unwrap();
+        assert_eq!(slice.name, "hitbox");
+        assert_eq!(slice.flags(), SliceFlags::NINE_PATCH | SliceFlags::HAS_PIVOT);
+        assert_eq!(slice.keys().len(), 2);
+
+        let first = SliceKey { frame: 0, bounds: rect(1, 2, 10, 20), center: Some(rect(0, 0, 4, 4)), pivot: Some(Point { x: 5, y: 6 }) };
+        assert_eq!(slice.key_for_frame(0), Some(first));
+        assert_eq!(slice.key_for_frame(3), Some(first));
+        let second = slice.key_for_frame(4).unwrap();
+        assert_eq!(second.bounds, rect(-1, -2, 0, 0));
+        assert_eq!(slice.key_for_frame(100), Some(second));
+    }
+
+    #[test]
+    fn plain_slice() {
+        let data = [longs(&[2, 0, 0]), string("a"), longs(&[3, 0, 0, 1, 1]), longs(&[5, 0, 0, 2, 2])].concat();
+        let slice = Slice::new(&data).unwrap();
+        assert_eq!(slice.key_for_frame(2), None);
+        let key = slice.key_for_frame(9).unwrap();
+        assert_eq!((key.frame, key.bounds, key.center, key.pivot), (5, rect(0, 0, 2, 2), None, None));
+
+        let e = Slice::new(&data[..data.len() - 1]).unwrap_err();
+        assert_eq!(e.kind, ErrorKind::Truncated { needed: 40, available: 39 });
+    }
+
+    #[test]
+    fn slice_size_out_of_range() {
+        let data = [longs(&[1, 0, 0]), string("a"), longs(&[0, 0, 0, 1]), 0x8000_0000u32.to_le_bytes().to_vec()].concat();
+        let e = Slice::new(&data).unwrap_err();
+        assert_eq!(e.kind, ErrorKind::BadEnumValue { field: "slice height", value: 0x8000_0000 });
+        assert_eq!(e.offset, 12 + 3 + 16);
+    }
+}
@@ -0,0 +1,206 @@
+use core::fmt::{self, Debug, Formatter};
+
+use zerocopy::*;
+
+use crate::error::Error;
+use crate::parser::bytes::ByteReader;
+use crate::parser::chunk::palette::Rgba;
+use crate::parser::chunk::user_data::{UserData, UserDataRun};
+
+/// How the frames of a [`Tag`] are played.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoopDirection {
+    Forward,
+    Reverse,
+    PingPong,
+    PingPongReverse,
+}
+
+impl TryFrom<u8> for LoopDirection {
+    type Error = Error;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        Ok(match value {
+            0 => LoopDirection::Forward,
+            1 => LoopDirection::Reverse,
+            2 => LoopDirection::PingPong,
+            3 => LoopDirection::PingPongReverse,
+            _ => return Err(Error::bad_enum(0, "loop direction", value as u32)),
+        })
+    }
+}
+
+#[derive(Debug, FromBytes, KnownLayout, Immutable, Unaligned)]
+#[repr(C)]
+struct TagHeader {
+    from: U16<LE>,
+    to: U16<LE>,
+    direction: u8,
+    repeat: U16<LE>,
+    _reserved: [u8; 6],
+    color: [u8; 3],
+    _extra: u8,
+}
+
+/// An animation tag, covering the frames `from..=to`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tag<'a> {
+    pub from: u16,
+    pub to: u16,
+    pub direction: LoopDirection,
+    /// Times the animation is played, 0 to repeat forever
+    pub repeat: u16,
+    pub name: &'a str,
+    /// Deprecated tag color, the user data of the tag has the current one
+    pub legacy_color: Rgba,
+    user_data: Option<UserData<'a>>,
+}
+
+impl<'a> Tag<'a> {
+    /// User data of this tag, see [`user_data`](super::user_data).
+    pub fn user_data(&self) -> Option<&UserData<'a>> {
+        self.user_data.as_ref()
+    }
+}
+
+/// Tags chunk (0x2018), holding every tag of the sprite.
+#[derive(Clone)]
+pub struct Tags<'a> {
+    count: u16,
+    data: &'a [u8],
+    /// Rest of the frame, starting with the user data chunks of the tags
+    pub(crate) following: &'a [u8],
+}
+
+impl<'a> Tags<'a> {
+    /// Checks every tag.
+    pub fn new(data: &'a [u8]) -> Result<Self, Error> {
+        let mut reader = ByteReader::new(data);
+        let count = reader.u16()?;
+        reader.bytes(8)?;
+        let tags = reader.remaining();
+        for _ in 0..count {
+            read_tag(&mut reader)?;
+        }
+        Ok(Tags { count, data: &tags[..reader.position() - 10], following: &[] })
+    }
+
+    /// Number of tags
+    pub fn len(&self) -> u16 {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    pub fn iter(&self) -> TagIterator<'a> {
+        TagIterator {
+            reader: ByteReader::new(self.data),
+            remaining: self.count,
+            user_data: UserDataRun::new(self.following),
+        }
+    }
+
+    /// Finds a tag by its exact, case sensitive name.
+    pub fn get(&self, name: &str) -> Option<Tag<'a>> {
+        self.iter().find(|tag| tag.name == name)
+    }
+}
+
+impl Debug for Tags<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<'a> IntoIterator for &Tags<'a> {
+    type Item = Tag<'a>;
+    type IntoIter = TagIterator<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+fn read_tag<'a>(reader: &mut ByteReader<'a>) -> Result<Tag<'a>, Error> {
+    let start = reader.position();
+    let header = reader.read::<TagHeader>()?;
+    let direction = LoopDirection::try_from(header.direction).map_err(|e| e.offset_by(start + 4))?;
+    let [r, g, b] = header.color;
+    Ok(Tag {
+        from: header.from.get(),
+        to: header.to.get(),
+        direction,
+        repeat: header.repeat.get(),
+        name: reader.string()?,
+        legacy_color: Rgba::new(r, g, b, 255),
+        user_data: None,
+    })
+}
+
+/// Iterator over the tags of a [`Tags`] chunk.
+#[derive(Debug, Clone)]
+pub struct TagIterator<'a> {
+    reader: ByteReader<'a>,
+    remaining: u16,
+    user_data: UserDataRun<'a>,
+}
+
+impl<'a> Iterator for TagIterator<'a> {
+    type Item = Tag<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None
+        }
+        self.remaining -= 1;
+        let tag = read_tag(&mut self.reader).ok()?;
+        Some(Tag { user_data: self.user_data.pop(), ..tag })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining as usize, Some(self.remaining as usize))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::error::ErrorKind;
+    use crate::test_util::string;
+
+    fn tag(from: u16, to: u16, direction: u8, repeat: u16, name: &str) -> std::vec::Vec<u8> {
+        [
+            &from.to_le_bytes()[..], &to.to_le_bytes(), &[direction], &repeat.to_le_bytes(),
+            &[0; 6], &[10, 20, 30, 0], &string(name),
+        ].concat()
+    }
+
+    #[test]
+    fn tags() {
+        let data = [&[2, 0][..], &[0; 8], &tag(0, 3, 2, 4, "walk"), &tag(4, 4, 3, 0, "attack")].concat();
+        let tags = Tags::new(&data).unwrap();
+        assert_eq!(tags.len(), 2);
+        assert_eq!(tags.get("attack"), Some(Tag {
+            from: 4,
+            to: 4,
+            direction: LoopDirection::PingPongReverse,
+            repeat: 0,
+            name: "attack",
+            legacy_color: Rgba::new(10, 20, 30, 255),
+            user_data: None,
+        }));
+        let walk = tags.iter().next().unwrap();
+        assert_eq!((walk.from, walk.to, walk.direction, walk.repeat), (0, 3, LoopDirection::PingPong, 4));
+        assert_eq!(tags.get("Walk"), None);
+    }
+
+    #[test]
+    fn invalid_loop_direction() {
+        let data = [&[2, 0][..], &[0; 8], &tag(0, 3, 0, 0, "a"), &tag(0, 3, 4, 0, "b")].concat();
+        let e = Tags::new(&data).unwrap_err();
+        assert_eq!(e.kind, ErrorKind::BadEnumValue { field: "loop direction", value: 4 });
+        assert_eq!(e.offset, 10 + 20 + 4);
+    }
+}
@@ -0,0 +1,225 @@
+use zerocopy::*;
+
+use crate::error::{Error, ErrorKind};
+use crate::parser::bytes::ByteReader;
+use crate::parser::chunk::image_len;
+use crate::parser::inflate::inflate_exact;
+
+#[derive(Debug, FromBytes, KnownLayout, Immutable, Unaligned)]
+#[repr(C)]
+pub struct TilemapHeader {
+    width: U16<LE>,
+    height: U16<LE>,
+    bits_per_tile: U16<LE>,
+    id_mask: U32<LE>,
+    flip_x_mask: U32<LE>,
+    flip_y_mask: U32<LE>,
+    diagonal_mask: U32<LE>,
+    _reserved: [u8; 10],
+}
+
+impl TilemapHeader {
+    /// Width in tiles
+    pub fn width(&self) -> u16 {
+        self.width.get()
+    }
+
+    /// Height in tiles
+    pub fn height(&self) -> u16 {
+        self.height.get()
+    }
+
+    /// Bits per tile, 8, 16 or 32. Aseprite currently always writes 32.
+    pub fn bits_per_tile(&self) -> u16 {
+        self.bits_per_tile.get()
+    }
+
+    pub fn id_mask(&self) -> u32 {
+        self.id_mask.get()
+    }
+
+    pub fn flip_x_mask(&self) -> u32 {
+        self.flip_x_mask.get()
+    }
+
+    pub fn flip_y_mask(&self) -> u32 {
+        self.flip_y_mask.get()
+    }
+
+    pub fn diagonal_mask(&self) -> u32 {
+        self.diagonal_mask.get()
+    }
+
+    /// Splits a raw tile value with the bitmasks of this tilemap.
+    pub fn tile_ref(&self, value: u32) -> TileRef {
+        let id_mask = self.id_mask();
+        TileRef {
+            id: (value & id_mask).checked_shr(id_mask.trailing_zeros()).unwrap_or(0),
+            flip_x: value & self.flip_x_mask() != 0,
+            flip_y: value & self.flip_y_mask() != 0,
+            diagonal: value & self.diagonal_mask() != 0,
+        }
+    }
+}
+
+/// A tile placed in a tilemap cel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TileRef {
+    /// Index of the tile in the tileset of the layer
+    pub id: u32,
+    pub flip_x: bool,
+    pub flip_y: bool,
+    /// Swaps the X and Y axes, applied before the other flips
+    pub diagonal: bool,
+}
+
+/// Body of a compressed tilemap cel (cel type 3).
+#[derive(Debug, Clone)]
+pub struct TilemapCel<'a> {
+    pub header: &'a TilemapHeader,
+    data: &'a [u8],
+}
+
+impl<'a> TilemapCel<'a> {
+    /// Reads the tilemap header, checking the number of bits per tile.
+    pub fn new(data: &'a [u8]) -> Result<Self, Error> {
+        let mut reader = ByteReader::new(data);
+        let header = reader.read::<TilemapHeader>()?;
+        let bits = header.bits_per_tile();
+        if !matches!(bits, 8 | 16 | 32) {
+            return Err(Error::bad_enum(4, "bits per tile", bits as u32));
+        }
+        Ok(TilemapCel { header, data: reader.remaining() })
+    }
+
+    /// The zlib compressed tiles
+    pub fn compressed_data(&self) -> &'a [u8] {
+        self.data
+    }
+
+    /// Size in bytes of the decompressed tiles.
+    /// Fails with [`ErrorKind::ImageTooLarge`] when that doesn't fit in a `usize`.
+    pub fn data_len(&self) -> Result<usize, Error> {
+        image_len(self.header.width(), self.header.height(), self.bytes_per_tile())
+    }
+
+    fn bytes_per_tile(&self) -> usize {
+        self.header.bits_per_tile() as usize / 8
+    }
+
+    /// Decompresses the tiles into `buf`, which must hold at least
+    /// [`TilemapCel::data_len`] bytes.
+    pub fn decompress<'b>(&self, buf: &'b mut [u8]) -> Result<Tilemap<'a, 'b>, Error> {
+        let len = self.data_len()?;
+        if buf.len() < len {
+            return Err(Error::new(ErrorKind::BufferTooSmall { needed: len, available: buf.len() }, 0));
+        }
+        let buf = &mut buf[..len];
+        inflate_exact(self.data, buf)?;
+        Ok(Tilemap { header: self.header, data: buf, bytes_per_tile: self.bytes_per_tile() })
+    }
+}
+
+/// The decompressed tiles of a [`TilemapCel`], in rows from the top left.
+#[derive(Debug, Clone)]
+pub struct Tilemap<'a, 'b> {
+    pub header: &'a TilemapHeader,
+    data: &'b [u8],
+    bytes_per_tile: usize,
+}
+
+impl<'b> Tilemap<'_, 'b> {
+    /// The tile at column `x` and row `y`.
+    pub fn tile(&self, x: u16, y: u16) -> Option<TileRef> {
+        if x >= self.header.width() || y >= self.header.height() {
+            return None;
+        }
+        let index = y as usize * self.header.width() as usize + x as usize;
+        let start = index * self.bytes_per_tile;
+        let value = self.data.get(start..start + self.bytes_per_tile)?;
+        Some(self.header.tile_ref(read_value(value)))
+    }
+
+    pub fn tiles(&self) -> TileRefs<'_, 'b> {
+        TileRefs { header: self.header, data: self.data, bytes_per_tile: self.bytes_per_tile }
+    }
+}
+
+fn read_value(bytes: &[u8]) -> u32 {
+    bytes.iter().rev().fold(0, |value, &b| (value << 8) | b as u32)
+}
+
+/// Iterator over the tiles of a [`Tilemap`], in rows from the top left.
+#[derive(Debug, Clone)]
+pub struct TileRefs<'a, 'b> {
+    header: &'a TilemapHeader,
+    data: &'b [u8],
+    bytes_per_tile: usize,
+}
+
+impl Iterator for TileRefs<'_, '_> {
+    type Item = TileRef;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (value, rest) = self.data.split_at_checked(self.bytes_per_tile)?;
+        self.data = rest;
+        Some(self.header.tile_ref(read_value(value)))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.data.len() / self.bytes_per_tile;
+        (len, Some(len))
+    }
+}
+
+impl ExactSizeIterator for TileRefs<'_, '_> {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::zlib;
+    use std::vec::Vec;
+
+    fn header(width: u16, height: u16, bits: u16) -> Vec<u8> {
+        [
+            &width.to_le_bytes()[..], &height.to_le_bytes(), &bits.to_le_bytes(),
+            &0x1fff_ffffu32.to_le_bytes(), &0x2000_0000u32.to_le_bytes(),
+            &0x4000_0000u32.to_le_bytes(), &0x8000_0000u32.to_le_bytes(), &[0; 10],
+        ].concat()
+    }
+
+    #[test]
+    fn tile_refs() {
+        let tiles: Vec<u8> = [0u32, 5, 0x2000_0003, 0xe000_0001, 7, 0x4000_0002]
+            .iter().flat_map(|v| v.to_le_bytes()).collect();
+        let data = [header(3, 2, 32), zlib(&tiles)].concat();
+        let cel = TilemapCel::new(&data).unwrap();
+        assert_eq!((cel.header.width(), cel.header.height(), cel.header.bits_per_tile()), (3, 2, 32));
+        assert_eq!(cel.data_len(), Ok(24));
+
+        let mut buf = [0u8; 24];
+        let tilemap = cel.decompress(&mut buf).unwrap();
+        assert_eq!(tilemap.tiles().len(), 6);
+        assert_eq!(tilemap.tile(2, 0), Some(TileRef { id: 3, flip_x: true, flip_y: false, diagonal: false }));
+        assert_eq!(tilemap.tile(0, 1), Some(TileRef { id: 1, flip_x: true, flip_y: true, diagonal: true }));
+        assert_eq!(tilemap.tile(3, 0), None);
+        let ids: Vec<u32> = tilemap.tiles().map(|t| t.id).collect();
+        assert_eq!(ids, [0, 5, 3, 1, 7, 2]);
+
+        let e = cel.decompress(&mut [0; 23]).unwrap_err();
+        assert_eq!(e.kind, ErrorKind::BufferTooSmall { needed: 24, available: 23 });
+    }
+
+    #[test]
+    fn narrow_tiles() {
+        let data = [header(2, 1, 16), zlib(&[0x01, 0x20, 0x04, 0x00])].concat();
+        let cel = TilemapCel::new(&data).unwrap();
+        let mut buf = [0u8; 4];
+        let tiles: Vec<TileRef> = cel.decompress(&mut buf).unwrap().tiles().collect();
+        assert_eq!(tiles[0], TileRef { id: 0x2001, flip_x: false, flip_y: false, diagonal: false });
+        assert_eq!(tiles[1].id, 4);
+
+        let e = TilemapCel::new(&header(2, 1, 12)).unwrap_err();
+        assert_eq!((e.kind, e.offset), (ErrorKind::BadEnumValue { field: "bits per tile", value: 12 }, 4));
+    }
+}
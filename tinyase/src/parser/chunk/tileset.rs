@@ -0,0 +1,379 @@
+use core::fmt::{self, Debug, Formatter};
+
+use bitflags::bitflags;
+use zerocopy::*;
+
+use crate::error::{Error, ErrorKind};
+use crate::parser::ColorDepth;
+use crate::parser::bytes::ByteReader;
+use crate::parser::chunk::image_len;
+use crate::parser::chunk::user_data::{UserData, UserDataRun};
+use crate::parser::inflate::inflate_exact;
+
+bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct TilesetFlags : u32 {
+        const EXTERNAL_FILE = 1;
+        const EMBEDDED_TILES = 2;
+        /// Tile 0 is the empty tile. Without it the empty tile is 0xFFFFFFFF.
+        const EMPTY_TILE_IS_ZERO = 4;
+        const AUTO_FLIP_X = 8;
+        const AUTO_FLIP_Y = 16;
+        const AUTO_FLIP_D = 32;
+    }
+}
+
+#[derive(Debug, FromBytes, KnownLayout, Immutable, Unaligned)]
+#[repr(C)]
+pub struct TilesetHeader {
+    id: U32<LE>,
+    flags: U32<LE>,
+    tile_count: U32<LE>,
+    tile_width: U16<LE>,
+    tile_height: U16<LE>,
+    base_index: I16<LE>,
+    _reserved: [u8; 14],
+}
+
+impl TilesetHeader {
+    pub fn id(&self) -> u32 {
+        self.id.get()
+    }
+
+    pub fn flags(&self) -> TilesetFlags {
+        TilesetFlags::from_bits_truncate(self.flags.get())
+    }
+
+    pub fn tile_count(&self) -> u32 {
+        self.tile_count.get()
+    }
+
+    pub fn tile_width(&self) -> u16 {
+        self.tile_width.get()
+    }
+
+    pub fn tile_height(&self) -> u16 {
+        self.tile_height.get()
+    }
+
+    /// Number shown in the UI for the tile with index 1, only for display
+    pub fn base_index(&self) -> i16 {
+        self.base_index.get()
+    }
+}
+
+/// Reference to a tileset stored in another file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExternalTileset {
+    /// Entry ID in the external files chunk
+    pub file_id: u32,
+    /// Tileset ID in the external file
+    pub tileset_id: u32,
+}
+
+/// Tileset chunk (0x2023).
+///
+/// The tiles are stored as one compressed image, `tile_width` wide and
+/// `tile_height * tile_count` high, see [`Tileset::decompress`].
+#[derive(Clone)]
+pub struct Tileset<'a> {
+    pub header: &'a TilesetHeader,
+    pub name: &'a str,
+    external: Option<ExternalTileset>,
+    image: Option<&'a [u8]>,
+    pub(crate) user_data: Option<UserData<'a>>,
+    /// The user data chunks of the tiles, which follow the one of the tileset
+    pub(crate) tile_user_data: UserDataRun<'a>,
+}
+
+impl<'a> Tileset<'a> {
+    /// The tile image is only decompressed on demand, see [`Tileset::decompress`].
+    pub fn new(data: &'a [u8]) -> Result<Self, Error> {
+        let mut reader = ByteReader::new(data);
+        let header = reader.read::<TilesetHeader>()?;
+        let name = reader.string()?;
+        let flags = header.flags();
+        let external = match flags.contains(TilesetFlags::EXTERNAL_FILE) {
+            true => Some(ExternalTileset { file_id: reader.u32()?, tileset_id: reader.u32()? }),
+            false => None,
+        };
+        let image = match flags.contains(TilesetFlags::EMBEDDED_TILES) {
+            true => {
+                let len = reader.u32()?;
+                Some(reader.bytes(len as usize)?)
+            }
+            false => None,
+        };
+        Ok(Tileset { header, name, external, image, user_data: None, tile_user_data: UserDataRun::default() })
+    }
+
+    pub fn external(&self) -> Option<ExternalTileset> {
+        self.external
+    }
+
+    /// The zlib compressed tile image, when the tiles are stored in this file
+    pub fn compressed_image(&self) -> Option<&'a [u8]> {
+        self.image
+    }
+
+    /// Size in bytes of the decompressed tile image.
+    /// Fails with [`ErrorKind::ImageTooLarge`] when that doesn't fit in a `usize`.
+    pub fn image_len(&self, depth: ColorDepth) -> Result<usize, Error> {
+        tile_len(self.header, depth)?.checked_mul(self.header.tile_count() as usize)
+            .ok_or(Error::new(ErrorKind::ImageTooLarge, 0))
+    }
+
+    /// Decompresses the tile image into `buf`, which must hold at least
+    /// [`Tileset::image_len`] bytes. `depth` is the color depth of the sprite.
+    pub fn decompress<'b>(&self, depth: ColorDepth, buf: &'b mut [u8]) -> Result<TileImage<'a, 'b>, Error> {
+        let image = self.image.ok_or(Error::new(ErrorKind::MissingImage, 0))?;
+        let tile_len = tile_len(self.header, depth)?;
+        let len = self.image_len(depth)?;
+        if buf.len() < len {
+            return Err(Error::new(ErrorKind::BufferTooSmall { needed: len, available: buf.len() }, 0));
+        }
+        let buf = &mut buf[..len];
+        inflate_exact(image, buf)?;
+        Ok(TileImage {
+            data: buf,
+            width: self.header.tile_width(),
+            height: self.header.tile_height(),
+            depth,
+            tile_len,
+            user_data: self.tile_user_data.clone(),
+            user_data_starts: None,
+        })
+    }
+
+    /// User data of this tileset, see [`user_data`](super::user_data).
+    pub fn user_data(&self) -> Option<&UserData<'a>> {
+        self.user_data.as_ref()
+    }
+}
+
+impl Debug for Tileset<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Tileset")
+            .field("header", &self.header)
+            .field("name", &self.name)
+            .field("external", &self.external)
+            .field("image", &self.image)
+            .field("user_data", &self.user_data)
+            .finish()
+    }
+}
+
+fn tile_len(header: &TilesetHeader, depth: ColorDepth) -> Result<usize, Error> {
+    image_len(header.tile_width(), header.tile_height(), depth.bytes_per_pixel())
+}
+
+/// The decompressed tiles of a [`Tileset`].
+#[derive(Debug, Clone)]
+pub struct TileImage<'a, 'b> {
+    data: &'b [u8],
+    width: u16,
+    height: u16,
+    depth: ColorDepth,
+    /// Size in bytes of one tile
+    tile_len: usize,
+    user_data: UserDataRun<'a>,
+    /// Where the user data of each tile starts in `user_data`, see [`TileImage::index_user_data`]
+    user_data_starts: Option<&'b [u32]>,
+}
+
+impl<'a, 'b> TileImage<'a, 'b> {
+    /// Number of tiles
+    pub fn len(&self) -> usize {
+        self.data.len().checked_div(self.tile_len).unwrap_or(0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns tile `index`. Finding its user data walks the user data of the tiles
+    /// before it, unless [`TileImage::index_user_data`] was called.
+    pub fn tile(&self, index: u32) -> Option<Tile<'a, 'b>> {
+        let len = self.tile_len;
+        let start = (index as usize).checked_mul(len)?;
+        let pixels = self.data.get(start..start.checked_add(len)?)?;
+        let user_data = match self.user_data_starts {
+            Some(starts) => self.user_data.skip(*starts.get(index as usize)? as usize).pop(),
+            None => self.tiles().nth(index as usize)?.user_data,
+        };
+        Some(Tile { index, width: self.width, height: self.height, depth: self.depth, pixels, user_data })
+    }
+
+    /// Records where the user data of each tile starts in `starts`, so that
+    /// [`TileImage::tile`] takes the same time for every tile.
+    ///
+    /// Fails with [`ErrorKind::BufferTooSmall`] if `starts` can't hold one entry per tile.
+    pub fn index_user_data(&mut self, starts: &'b mut [u32]) -> Result<(), Error> {
+        let len = self.len();
+        if starts.len() < len {
+            return Err(Error::new(ErrorKind::BufferTooSmall { needed: len, available: starts.len() }, 0));
+        }
+        let mut run = self.user_data.clone();
+        for start in &mut starts[..len] {
+            *start = (self.user_data.len() - run.len()) as u32;
+            run.pop();
+        }
+        self.user_data_starts = Some(starts);
+        Ok(())
+    }
+
+    pub fn tiles(&self) -> Tiles<'a, 'b> {
+        Tiles { image: self.clone(), index: 0, remaining: self.len() }
+    }
+}
+
+/// Iterator over the tiles of a [`TileImage`], advancing through the strip and the
+/// user data of the tiles once.
+#[derive(Debug, Clone)]
+pub struct Tiles<'a, 'b> {
+    /// The tiles not handed out yet
+    image: TileImage<'a, 'b>,
+    index: u32,
+    remaining: usize,
+}
+
+impl<'a, 'b> Iterator for Tiles<'a, 'b> {
+    type Item = Tile<'a, 'b>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        let image = &mut self.image;
+        let (pixels, rest) = image.data.split_at_checked(image.tile_len)?;
+        image.data = rest;
+        let tile = Tile {
+            index: self.index,
+            width: image.width,
+            height: image.height,
+            depth: image.depth,
+            pixels,
+            user_data: image.user_data.pop(),
+        };
+        self.index += 1;
+        Some(tile)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl ExactSizeIterator for Tiles<'_, '_> {}
+
+/// One tile of a [`TileImage`].
+#[derive(Debug, Clone)]
+pub struct Tile<'a, 'b> {
+    pub index: u32,
+    pub width: u16,
+    pub height: u16,
+    pub depth: ColorDepth,
+    /// Pixels in rows from the top left, in the color depth of the sprite
+    pub pixels: &'b [u8],
+    user_data: Option<UserData<'a>>,
+}
+
+impl<'a> Tile<'a, '_> {
+    /// The bytes of the pixel at `(x, y)`, one byte per pixel for indexed sprites.
+    pub fn pixel(&self, x: u16, y: u16) -> Option<&[u8]> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        let bpp = self.depth.bytes_per_pixel();
+        let start = (y as usize * self.width as usize + x as usize) * bpp;
+        self.pixels.get(start..start + bpp)
+    }
+
+    /// User data of this tile, see [`user_data`](super::user_data).
+    pub fn user_data(&self) -> Option<&UserData<'a>> {
+        self.user_data.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parser::HeaderReader;
+    use crate::parser::chunk::ASEChunk;
+    use crate::test_util::{FileBuilder, chunk, string, tileset, zlib};
+    use std::vec::Vec;
+
+    #[test]
+    fn embedded_tiles() {
+        // 3 tiles of 2x3 indexed pixels
+        let pixels: Vec<u8> = (0..18).collect();
+        let image = zlib(&pixels);
+        let data = tileset(7, 2 | 4, 3, 2, 3, &[&(image.len() as u32).to_le_bytes()[..], &image].concat());
+        let t = Tileset::new(&data).unwrap();
+        assert_eq!((t.header.id(), t.header.tile_count(), t.header.tile_width(), t.header.tile_height()), (7, 3, 2, 3));
+        assert_eq!(t.header.base_index(), 1);
+        assert_eq!(t.header.flags(), TilesetFlags::EMBEDDED_TILES | TilesetFlags::EMPTY_TILE_IS_ZERO);
+        assert_eq!(t.name, "ground");
+        assert_eq!(t.external(), None);
+        assert_eq!(t.image_len(ColorDepth::Indexed), Ok(18));
+
+        let mut buf = [0u8; 32];
+        let tiles = t.decompress(ColorDepth::Indexed, &mut buf).unwrap();
+        assert_eq!(tiles.len(), 3);
+        let tile = tiles.tile(1).unwrap();
+        assert_eq!(tile.pixels, &pixels[6..12]);
+        assert_eq!(tile.pixel(1, 2), Some(&[11][..]));
+        assert_eq!(tile.pixel(2, 0), None);
+        assert!(tiles.tile(3).is_none());
+        assert_eq!(tiles.tiles().len(), 3);
+        assert!(tiles.tiles().map(|t| t.pixels).eq(pixels.chunks(6)));
+
+        let e = t.decompress(ColorDepth::Rgba, &mut buf).unwrap_err();
+        assert_eq!(e.kind, ErrorKind::BufferTooSmall { needed: 72, available: 32 });
+        let e = t.decompress(ColorDepth::Grayscale, &mut [0; 36]).unwrap_err();
+        assert_eq!(e.kind, ErrorKind::ImageSizeMismatch { expected: 36 });
+    }
+
+    #[test]
+    fn external_tiles() {
+        let data = tileset(7, 1, 4, 2, 3, &[&3u32.to_le_bytes()[..], &9u32.to_le_bytes()].concat());
+        let t = Tileset::new(&data).unwrap();
+        assert_eq!(t.external(), Some(ExternalTileset { file_id: 3, tileset_id: 9 }));
+        assert_eq!(t.compressed_image(), None);
+        assert_eq!(t.decompress(ColorDepth::Indexed, &mut []).unwrap_err().kind, ErrorKind::MissingImage);
+
+        let truncated = tileset(7, 1, 4, 2, 3, &[0; 7]);
+        assert!(matches!(Tileset::new(&truncated).unwrap_err().kind, ErrorKind::Truncated { .. }));
+    }
+
+    #[test]
+    fn tileset_and_tile_user_data() {
+        let image = zlib(&[0; 12]);
+        let payload = tileset(7, 2, 2, 2, 3, &[&(image.len() as u32).to_le_bytes()[..], &image].concat());
+        let text = |s: &str| chunk(0x2020, &[&1u32.to_le_bytes()[..], &string(s)].concat());
+        let data = FileBuilder::new()
+            .depth(8)
+            .frame(100, &[chunk(0x2023, &payload), text("set"), chunk(0x2020, &[0; 4]), text("tile 1")])
+            .build();
+        let r = HeaderReader::new(&data);
+        let Some(Ok(ASEChunk::Tileset(t))) = r.frames().next().unwrap().unwrap().chunks().next() else {
+            panic!("not a tileset")
+        };
+        assert_eq!(t.user_data().and_then(|u| u.text()), Some("set"));
+
+        let mut buf = [0u8; 12];
+        let tiles = t.decompress(r.color_depth(), &mut buf).unwrap();
+        assert_eq!(tiles.tile(0).unwrap().user_data().map(|u| u.text()), Some(None));
+        assert_eq!(tiles.tile(1).unwrap().user_data().and_then(|u| u.text()), Some("tile 1"));
+        let texts: Vec<_> = tiles.tiles().map(|t| (t.index, t.user_data().and_then(|u| u.text()))).collect();
+        assert_eq!(texts, [(0, None), (1, Some("tile 1"))]);
+
+        let (mut short, mut starts) = ([0; 1], [0; 4]);
+        let mut tiles = tiles.clone();
+        assert_eq!(tiles.index_user_data(&mut short).unwrap_err().kind, ErrorKind::BufferTooSmall { needed: 2, available: 1 });
+        tiles.index_user_data(&mut starts).unwrap();
+        assert_eq!(tiles.tile(1).unwrap().user_data().and_then(|u| u.text()), Some("tile 1"));
+        assert_eq!(tiles.tile(0).unwrap().user_data().map(|u| u.text()), Some(None));
+    }
+}
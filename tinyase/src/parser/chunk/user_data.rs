@@ -0,0 +1,574 @@
+//! User data chunks and the objects they belong to.
+//!
+//! A user data chunk belongs to the object read right before it. When chunks are read
+//! from a whole frame the association is done while decoding:
+//!
+//! - a layer gets the user data chunk that follows it,
+//! - a cel gets the one that follows it, after its cel extra chunk if there is one,
+//! - each tag of a tags chunk gets one of the user data chunks that follow, in order,
+//! - a slice gets the user data chunk that follows it,
+//! - a tileset gets the first user data chunk that follows it, and each of its tiles
+//!   one of the next ones, in order,
+//! - the sprite gets the one following the palette chunks of the first frame, see
+//!   [`HeaderReader::user_data`](crate::parser::HeaderReader::user_data).
+//!
+//! The streaming readers decode chunks one at a time, so their objects never carry
+//! user data.
+
+use bitflags::bitflags;
+
+use crate::error::{Error, ErrorKind};
+use crate::parser::bytes::ByteReader;
+use crate::parser::chunk::{ASEChunkReader, CHUNK_HEADER_SIZE};
+use crate::parser::chunk::palette::Rgba;
+use crate::parser::types::{Fixed, Point, Rect, Size};
+
+/// Nested vectors and maps deeper than this are rejected, so that a hostile file
+/// cannot overflow the stack.
+pub const MAX_PROPERTY_DEPTH: u8 = 16;
+
+bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct UserDataFlags : u32 {
+        const HAS_TEXT = 1;
+        const HAS_COLOR = 2;
+        const HAS_PROPERTIES = 4;
+    }
+}
+
+/// User data chunk (0x2020), holding the text, color and properties attached to the
+/// object read before it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UserData<'a> {
+    flags: UserDataFlags,
+    text: Option<&'a str>,
+    color: Option<Rgba>,
+    maps: u32,
+    properties: &'a [u8],
+}
+
+impl<'a> UserData<'a> {
+    /// Checks every property.
+    pub fn new(data: &'a [u8]) -> Result<Self, Error> {
+        let mut reader = ByteReader::new(data);
+        let flags = UserDataFlags::from_bits_truncate(reader.u32()?);
+        let text = match flags.contains(UserDataFlags::HAS_TEXT) {
+            true => Some(reader.string()?),
+            false => None,
+        };
+        let color = match flags.contains(UserDataFlags::HAS_COLOR) {
+            true => Some(*reader.read::<Rgba>()?),
+            false => None,
+        };
+        let (maps, properties) = match flags.contains(UserDataFlags::HAS_PROPERTIES) {
+            true => {
+                let start = reader.position();
+                let size = reader.u32()?;
+                let maps = reader.u32()?;
+                let len = (size as usize).checked_sub(8)
+                    .ok_or(Error::new(ErrorKind::InvalidPropertiesSize(size), start))?;
+                let offset = reader.position();
+                let properties = reader.bytes(len)?;
+                let mut maps_reader = ByteReader::new(properties);
+                for _ in 0..maps {
+                    read_map(&mut maps_reader).map_err(|e| e.offset_by(offset))?;
+                }
+                (maps, properties)
+            }
+            false => (0, &[][..]),
+        };
+        Ok(UserData { flags, text, color, maps, properties })
+    }
+
+    pub fn flags(&self) -> UserDataFlags {
+        self.flags
+    }
+
+    pub fn text(&self) -> Option<&'a str> {
+        self.text
+    }
+
+    pub fn color(&self) -> Option<Rgba> {
+        self.color
+    }
+
+    /// Iterates over the properties maps as `(key, map)`. Key 0 holds the user
+    /// properties, other keys are extension entry IDs from the external files chunk.
+    pub fn properties(&self) -> PropertyMaps<'a> {
+        PropertyMaps { reader: ByteReader::new(self.properties), remaining: self.maps }
+    }
+
+    /// The user properties, the map with key 0.
+    pub fn user_properties(&self) -> Option<PropertyMap<'a>> {
+        self.properties().find(|(key, _)| *key == 0).map(|(_, map)| map)
+    }
+}
+
+/// The user data chunk at the start of `rest`, after any cel extra chunks when
+/// `skip_cel_extra` is set. A user data chunk that cannot be decoded is ignored here,
+/// iterating over the chunks reports the error.
+pub(crate) fn following(mut rest: &[u8], skip_cel_extra: bool) -> Option<UserData<'_>> {
+    loop {
+        let reader = ASEChunkReader::new(rest).ok()?;
+        match reader.0.chunk_type() {
+            0x2020 => return UserData::new(reader.1).ok(),
+            0x2006 if skip_cel_extra => rest = &rest[CHUNK_HEADER_SIZE + reader.1.len()..],
+            _ => return None,
+        }
+    }
+}
+
+/// The run of user data chunks following a chunk that holds several objects, one
+/// chunk per object.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct UserDataRun<'a> {
+    rest: &'a [u8],
+}
+
+impl<'a> UserDataRun<'a> {
+    pub fn new(rest: &'a [u8]) -> Self {
+        UserDataRun { rest }
+    }
+
+    /// User data of the next object, `None` once the run has ended.
+    pub fn pop(&mut self) -> Option<UserData<'a>> {
+        let reader = ASEChunkReader::new(self.rest).ok().filter(|r| r.0.chunk_type() == 0x2020);
+        let Some(reader) = reader else {
+            self.rest = &[];
+            return None;
+        };
+        self.rest = &self.rest[CHUNK_HEADER_SIZE + reader.1.len()..];
+        UserData::new(reader.1).ok()
+    }
+
+    /// Bytes left in the run
+    pub fn len(&self) -> usize {
+        self.rest.len()
+    }
+
+    /// The rest of the run after its first `offset` bytes
+    pub fn skip(&self, offset: usize) -> Self {
+        UserDataRun { rest: self.rest.get(offset..).unwrap_or_default() }
+    }
+}
+
+fn read_map<'a>(reader: &mut ByteReader<'a>) -> Result<(u32, PropertyMap<'a>), Error> {
+    let key = reader.u32()?;
+    let count = reader.u32()?;
+    let map = read_map_body(reader, count, 0)?;
+    Ok((key, map))
+}
+
+/// Reads `count` properties, returning a map over exactly the bytes they take.
+fn read_map_body<'a>(reader: &mut ByteReader<'a>, count: u32, depth: u8) -> Result<PropertyMap<'a>, Error> {
+    let start = reader.position();
+    let data = reader.remaining();
+    for _ in 0..count {
+        read_property(reader, depth)?;
+    }
+    Ok(PropertyMap { count, depth, data: &data[..reader.position() - start] })
+}
+
+fn read_property<'a>(reader: &mut ByteReader<'a>, depth: u8) -> Result<(&'a str, PropertyValue<'a>), Error> {
+    let name = reader.string()?;
+    let value_type = reader.u16()?;
+    Ok((name, read_value(reader, value_type, depth)?))
+}
+
+/// Reads a value of `value_type`. Vectors and maps are checked to the end, so that
+/// iterating over them later cannot fail.
+fn read_value<'a>(reader: &mut ByteReader<'a>, value_type: u16, depth: u8) -> Result<PropertyValue<'a>, Error> {
+    let start = reader.position();
+    Ok(match value_type {
+        0x0001 => PropertyValue::Bool(reader.u8()? != 0),
+        0x0002 => PropertyValue::I8(reader.u8()? as i8),
+        0x0003 => PropertyValue::U8(reader.u8()?),
+        0x0004 => PropertyValue::I16(reader.u16()? as i16),
+        0x0005 => PropertyValue::U16(reader.u16()?),
+        0x0006 => PropertyValue::I32(reader.i32()?),
+        0x0007 => PropertyValue::U32(reader.u32()?),
+        0x0008 => PropertyValue::I64(reader.u64()? as i64),
+        0x0009 => PropertyValue::U64(reader.u64()?),
+        0x000A => PropertyValue::Fixed(Fixed::from_bits(reader.i32()?)),
+        0x000B => PropertyValue::F32(f32::from_bits(reader.u32()?)),
+        0x000C => PropertyValue::F64(f64::from_bits(reader.u64()?)),
+        0x000D => PropertyValue::String(reader.string()?),
+        0x000E => PropertyValue::Point(read_point(reader)?),
+        0x000F => PropertyValue::Size(read_size(reader)?),
+        0x0010 => PropertyValue::Rect(Rect { origin: read_point(reader)?, size: read_size(reader)? }),
+        0x0011 | 0x0012 if depth >= MAX_PROPERTY_DEPTH => {
+            return Err(Error::new(ErrorKind::NestingTooDeep(MAX_PROPERTY_DEPTH), start))
+        }
+        0x0011 => {
+            let count = reader.u32()?;
+            let element_type = reader.u16()?;
+            let data = reader.remaining();
+            let body_start = reader.position();
+            let mut elements = VectorElements { reader: reader.clone(), element_type, remaining: count, depth: depth + 1 };
+            for _ in 0..count {
+                elements.read()?;
+            }
+            *reader = elements.reader;
+            let data = &data[..reader.position() - body_start];
+            PropertyValue::Vector(PropertyVector { count, element_type, depth: depth + 1, data })
+        }
+        0x0012 => {
+            let count = reader.u32()?;
+            PropertyValue::Map(read_map_body(reader, count, depth + 1)?)
+        }
+        0x0013 => PropertyValue::Uuid(reader.read::<[u8; 16]>()?),
+        _ => return Err(Error::bad_enum(start, "property type", value_type as u32)),
+    })
+}
+
+fn read_point(reader: &mut ByteReader<'_>) -> Result<Point, Error> {
+    Ok(Point { x: reader.i32()?, y: reader.i32()? })
+}
+
+fn read_size(reader: &mut ByteReader<'_>) -> Result<Size, Error> {
+    Ok(Size { width: reader.i32()?, height: reader.i32()? })
+}
+
+/// A property value, borrowing strings and nested containers from the file.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PropertyValue<'a> {
+    Bool(bool),
+    I8(i8),
+    U8(u8),
+    I16(i16),
+    U16(u16),
+    I32(i32),
+    U32(u32),
+    I64(i64),
+    U64(u64),
+    Fixed(Fixed),
+    F32(f32),
+    F64(f64),
+    String(&'a str),
+    Point(Point),
+    Size(Size),
+    Rect(Rect),
+    Vector(PropertyVector<'a>),
+    Map(PropertyMap<'a>),
+    Uuid(&'a [u8; 16]),
+}
+
+/// Iterator over the properties maps of a [`UserData`] chunk.
+#[derive(Debug, Clone)]
+pub struct PropertyMaps<'a> {
+    reader: ByteReader<'a>,
+    remaining: u32,
+}
+
+impl<'a> Iterator for PropertyMaps<'a> {
+    type Item = (u32, PropertyMap<'a>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None
+        }
+        self.remaining -= 1;
+        read_map(&mut self.reader).ok()
+    }
+}
+
+/// A properties map, iterated as `(name, value)`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PropertyMap<'a> {
+    count: u32,
+    depth: u8,
+    data: &'a [u8],
+}
+
+impl<'a> PropertyMap<'a> {
+    /// Number of properties
+    pub fn len(&self) -> u32 {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    pub fn iter(&self) -> PropertyIterator<'a> {
+        PropertyIterator { reader: ByteReader::new(self.data), remaining: self.count, depth: self.depth }
+    }
+
+    /// Finds a property by name.
+    pub fn get(&self, name: &str) -> Option<PropertyValue<'a>> {
+        self.iter().find(|(n, _)| *n == name).map(|(_, value)| value)
+    }
+}
+
+impl<'a> IntoIterator for &PropertyMap<'a> {
+    type Item = (&'a str, PropertyValue<'a>);
+    type IntoIter = PropertyIterator<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Iterator over the properties of a [`PropertyMap`].
+#[derive(Debug, Clone)]
+pub struct PropertyIterator<'a> {
+    reader: ByteReader<'a>,
+    remaining: u32,
+    depth: u8,
+}
+
+impl<'a> Iterator for PropertyIterator<'a> {
+    type Item = (&'a str, PropertyValue<'a>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None
+        }
+        self.remaining -= 1;
+        read_property(&mut self.reader, self.depth).ok()
+    }
+}
+
+/// A vector property, iterated by value.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PropertyVector<'a> {
+    count: u32,
+    element_type: u16,
+    depth: u8,
+    data: &'a [u8],
+}
+
+impl<'a> PropertyVector<'a> {
+    /// Number of elements
+    pub fn len(&self) -> u32 {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// Type of every element, or 0 when each element carries its own type
+    pub fn element_type(&self) -> u16 {
+        self.element_type
+    }
+
+    pub fn iter(&self) -> VectorElements<'a> {
+        VectorElements {
+            reader: ByteReader::new(self.data),
+            element_type: self.element_type,
+            remaining: self.count,
+            depth: self.depth,
+        }
+    }
+}
+
+impl<'a> IntoIterator for &PropertyVector<'a> {
+    type Item = PropertyValue<'a>;
+    type IntoIter = VectorElements<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Iterator over the elements of a [`PropertyVector`].
+#[derive(Debug, Clone)]
+pub struct VectorElements<'a> {
+    reader: ByteReader<'a>,
+    element_type: u16,
+    remaining: u32,
+    depth: u8,
+}
+
+impl<'a> VectorElements<'a> {
+    fn read(&mut self) -> Result<PropertyValue<'a>, Error> {
+        let element_type = match self.element_type {
+            0 => self.reader.u16()?,
+            element_type => element_type,
+        };
+        read_value(&mut self.reader, element_type, self.depth)
+    }
+}
+
+impl<'a> Iterator for VectorElements<'a> {
+    type Item = PropertyValue<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None
+        }
+        self.remaining -= 1;
+        self.read().ok()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::string;
+    use std::vec::Vec;
+
+    fn property(name: &str, value_type: u16, value: &[u8]) -> Vec<u8> {
+        [&string(name)[..], &value_type.to_le_bytes(), value].concat()
+    }
+
+    fn user_data(flags: u32, rest: &[u8], maps: &[(u32, u32, Vec<u8>)]) -> Vec<u8> {
+        let mut out = [&flags.to_le_bytes()[..], rest].concat();
+        if !maps.is_empty() {
+            let body: Vec<u8> = maps.iter()
+                .flat_map(|(key, count, props)| [&key.to_le_bytes()[..], &count.to_le_bytes(), props].concat())
+                .collect();
+            out.extend_from_slice(&(body.len() as u32 + 8).to_le_bytes());
+            out.extend_from_slice(&(maps.len() as u32).to_le_bytes());
+            out.extend_from_slice(&body);
+        }
+        out
+    }
+
+    #[test]
+    fn text_and_color() {
+        let data = user_data(3, &[&string("hello")[..], &[1, 2, 3, 4]].concat(), &[]);
+        let u = UserData::new(&data).unwrap();
+        assert_eq!(u.text(), Some("hello"));
+        assert_eq!(u.color(), Some(Rgba::new(1, 2, 3, 4)));
+        assert_eq!(u.properties().count(), 0);
+
+        let u = UserData::new(&[2, 0, 0, 0, 9, 9, 9, 9]).unwrap();
+        assert_eq!((u.text(), u.color()), (None, Some(Rgba::new(9, 9, 9, 9))));
+    }
+
+    #[test]
+    fn every_property_type() {
+        let mut rect = Vec::new();
+        for v in [1i32, -2, 3, 4] {
+            rect.extend_from_slice(&v.to_le_bytes());
+        }
+        let uuid: Vec<u8> = (0..16).collect();
+        let props = [
+            property("bool", 0x01, &[1]),
+            property("i8", 0x02, &[0xFF]),
+            property("u8", 0x03, &[200]),
+            property("i16", 0x04, &(-300i16).to_le_bytes()),
+            property("u16", 0x05, &60000u16.to_le_bytes()),
+            property("i32", 0x06, &(-70000i32).to_le_bytes()),
+            property("u32", 0x07, &4_000_000_000u32.to_le_bytes()),
+            property("i64", 0x08, &(-5i64).to_le_bytes()),
+            property("u64", 0x09, &u64::MAX.to_le_bytes()),
+            property("fixed", 0x0A, &0x0001_8000i32.to_le_bytes()),
+            property("float", 0x0B, &1.5f32.to_le_bytes()),
+            property("double", 0x0C, &(-0.25f64).to_le_bytes()),
+            property("string", 0x0D, &string("speed")),
+            property("point", 0x0E, &rect[..8]),
+            property("size", 0x0F, &rect[8..]),
+            property("rect", 0x10, &rect),
+            property("uuid", 0x13, &uuid),
+        ].concat();
+        let data = user_data(4, &[], &[(0, 17, props)]);
+        let u = UserData::new(&data).unwrap();
+        let map = u.user_properties().unwrap();
+        assert_eq!(map.len(), 17);
+
+        let values: Vec<_> = map.iter().map(|(_, v)| v).collect();
+        assert_eq!(values, [
+            PropertyValue::Bool(true),
+            PropertyValue::I8(-1),
+            PropertyValue::U8(200),
+            PropertyValue::I16(-300),
+            PropertyValue::U16(60000),
+            PropertyValue::I32(-70000),
+            PropertyValue::U32(4_000_000_000),
+            PropertyValue::I64(-5),
+            PropertyValue::U64(u64::MAX),
+            PropertyValue::Fixed(Fixed::from_bits(0x0001_8000)),
+            PropertyValue::F32(1.5),
+            PropertyValue::F64(-0.25),
+            PropertyValue::String("speed"),
+            PropertyValue::Point(Point { x: 1, y: -2 }),
+            PropertyValue::Size(Size { width: 3, height: 4 }),
+            PropertyValue::Rect(Rect { origin: Point { x: 1, y: -2 }, size: Size { width: 3, height: 4 } }),
+            PropertyValue::Uuid(uuid.as_slice().try_into().unwrap()),
+        ]);
+        assert_eq!(map.get("fixed").map(|v| match v { PropertyValue::Fixed(f) => f.to_f32(), _ => 0.0 }), Some(1.5));
+    }
+
+    #[test]
+    fn vectors_and_nested_maps() {
+        // vector of u8, mixed vector, nested map holding a string
+        let typed = [&3u32.to_le_bytes()[..], &0x03u16.to_le_bytes(), &[1, 2, 3]].concat();
+        let mixed = [
+            &2u32.to_le_bytes()[..], &0u16.to_le_bytes(),
+            &0x01u16.to_le_bytes(), &[0],
+            &0x0Du16.to_le_bytes(), &string("x"),
+        ].concat();
+        let nested = [&1u32.to_le_bytes()[..], &property("name", 0x0D, &string("boss"))].concat();
+        let props = [property("typed", 0x11, &typed), property("mixed", 0x11, &mixed), property("nested", 0x12, &nested)].concat();
+        let data = user_data(4, &[], &[(7, 0, Vec::new()), (0, 3, props)]);
+        let u = UserData::new(&data).unwrap();
+
+        let keys: Vec<u32> = u.properties().map(|(key, _)| key).collect();
+        assert_eq!(keys, [7, 0]);
+        let map = u.user_properties().unwrap();
+
+        let Some(PropertyValue::Vector(typed)) = map.get("typed") else { panic!("not a vector") };
+        assert_eq!(typed.element_type(), 3);
+        assert_eq!(typed.iter().collect::<Vec<_>>(), [PropertyValue::U8(1), PropertyValue::U8(2), PropertyValue::U8(3)]);
+
+        let Some(PropertyValue::Vector(mixed)) = map.get("mixed") else { panic!("not a vector") };
+        assert_eq!(mixed.iter().collect::<Vec<_>>(), [PropertyValue::Bool(false), PropertyValue::String("x")]);
+
+        let Some(PropertyValue::Map(nested)) = map.get("nested") else { panic!("not a map") };
+        assert_eq!(nested.get("name"), Some(PropertyValue::String("boss")));
+    }
+
+    #[test]
+    fn invalid_properties() {
+        let data = user_data(4, &[], &[(0, 1, property("a", 0x14, &[]))]);
+        let e = UserData::new(&data).unwrap_err();
+        assert_eq!(e.kind, ErrorKind::BadEnumValue { field: "property type", value: 0x14 });
+        assert_eq!(e.offset, 12 + 8 + 5);
+
+        let data = user_data(4, &[], &[(0, 2, property("a", 0x01, &[1]))]);
+        assert!(matches!(UserData::new(&data).unwrap_err().kind, ErrorKind::Truncated { .. }));
+
+        // the size doesn't even cover its own fields
+        let data = [&4u32.to_le_bytes()[..], &7u32.to_le_bytes(), &0u32.to_le_bytes()].concat();
+        let e = UserData::new(&data).unwrap_err();
+        assert_eq!((e.kind, e.offset), (ErrorKind::InvalidPropertiesSize(7), 4));
+
+        // maps nested deeper than the limit
+        let mut value = [&0u32.to_le_bytes()[..]].concat();
+        for _ in 0..=MAX_PROPERTY_DEPTH {
+            value = [&1u32.to_le_bytes()[..], &property("m", 0x12, &value)].concat();
+        }
+        let data = user_data(4, &[], &[(0, 1, property("m", 0x12, &value))]);
+        assert_eq!(UserData::new(&data).unwrap_err().kind, ErrorKind::NestingTooDeep(MAX_PROPERTY_DEPTH));
+    }
+
+    #[test]
+    fn user_data_follows_its_object() {
+        use crate::parser::HeaderReader;
+        use crate::parser::chunk::ASEChunk;
+        use crate::test_util::{FileBuilder, chunk};
+
+        let layer = chunk(0x2004, &[&[0u8; 16][..], &string("a")].concat());
+        let cel = chunk(0x2005, &[&[0u8; 16][..], &[1, 0, 0, 0]].concat());
+        let text = |s: &str| chunk(0x2020, &[&1u32.to_le_bytes()[..], &string(s)].concat());
+        let data = FileBuilder::new()
+            .frame(100, &[
+                layer.clone(), text("layer"),
+                layer,
+                cel.clone(), chunk(0x2006, &[0; 36]), text("cel"),
+                cel,
+            ])
+            .build();
+        let r = HeaderReader::new(&data);
+        let frame = r.frames().next().unwrap().unwrap();
+        let texts: Vec<_> = frame.chunks().map(|chunk| match chunk.unwrap() {
+            ASEChunk::Layer(layer) => layer.user_data().and_then(|u| u.text()),
+            ASEChunk::Cel(cel) => cel.user_data().and_then(|u| u.text()),
+            _ => Some("-"),
+        }).collect();
+        assert_eq!(texts, [Some("layer"), Some("-"), None, Some("cel"), Some("-"), Some("-"), None]);
+    }
+}
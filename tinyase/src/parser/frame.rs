@@ -1,79 +1,174 @@
-use thiserror::Error;
 use zerocopy::*;
-use core::iter::Iterator;
 
-use crate::parser::chunk::ChunkIterator;
+use crate::error::{Error, ErrorKind};
+use crate::parser::{FRAME_HEADER_SIZE, HeaderFlags, chunk::ChunkIterator, ref_prefix};
 
+pub mod index;
+pub use index::FrameIndex;
 
+const FRAME_MAGIC: u16 = 0xF1FA;
+
+
+/// Iterates over the frames of a file.
+///
+/// A frame whose header cannot be read ends the iteration after the error is returned.
 pub struct FrameListIterator<'a> {
     pub(crate) rest: &'a [u8],
     pub(crate) remaining: u16,
+    /// Offset of `rest` from the start of the file, used for error reporting
+    pub(crate) offset: usize,
+    pub(crate) index: u16,
+    /// Header speed, the duration of frames that don't specify their own
+    pub(crate) speed: u16,
+    /// Header flags, which change how some chunks are laid out
+    pub(crate) flags: HeaderFlags,
 }
 
 impl<'a> Iterator for FrameListIterator<'a> {
-    type Item = FrameReader<'a>;
+    type Item = Result<FrameReader<'a>, Error>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.remaining <= 0 {
+        if self.remaining == 0 {
             return None
         }
-        let Some(fr) = FrameReader::new(self.rest).ok() else {
-            return None
+        let fr = match FrameReader::new(self.rest, self.offset, self.index, self.speed, self.flags) {
+            Ok(fr) => fr,
+            Err(e) => {
+                self.remaining = 0;
+                return Some(Err(e.offset_by(self.offset).in_frame(self.index)));
+            }
         };
         let frame_size = fr.size() as usize;
         self.rest = &self.rest[frame_size..];
-        self.remaining = self.remaining - 1;
+        self.offset += frame_size;
+        self.index += 1;
+        self.remaining -= 1;
         
-        Some(fr)
+        Some(Ok(fr))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.remaining as usize))
     }
 }
 
 pub struct FrameReader<'a> {
     pub frame: &'a ASEFrameHeader,
+    /// Chunk data of this frame, up to the end of the frame
     pub rest: &'a[u8],
+    offset: usize,
+    index: u16,
+    speed: u16,
+    flags: HeaderFlags,
 }
 
 impl<'a> FrameReader<'a> {
-    fn new(data: &'a[u8]) -> Result<FrameReader<'a>, FrameParseError> {
-        parse_frame(data).map(|(frame, rest)| FrameReader{frame: frame, rest})
+    /// Reads the frame at the start of `data`, which must hold the whole frame.
+    pub(crate) fn new(data: &'a[u8], offset: usize, index: u16, speed: u16, flags: HeaderFlags) -> Result<FrameReader<'a>, Error> {
+        let (frame, _) = parse_frame(data)?;
+        let size = frame.num_bytes();
+        if (size as usize) < FRAME_HEADER_SIZE {
+            return Err(Error::new(ErrorKind::InvalidFrameSize(size), 0));
+        }
+        if size as usize > data.len() {
+            return Err(Error::truncated(0, size as usize, data.len()));
+        }
+        let rest = &data[FRAME_HEADER_SIZE..size as usize];
+        Ok(FrameReader { frame, rest, offset, index, speed, flags })
     }
 
     fn size(&self) -> u32 {
-        self.frame.num_bytes
+        self.frame.num_bytes()
+    }
+
+    /// Index of this frame, starting at 0
+    pub fn index(&self) -> u16 {
+        self.index
+    }
+
+    /// Offset of the frame header from the start of the file
+    pub fn byte_offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Frame duration in milliseconds. Frames that don't set a duration fall back
+    /// to the deprecated speed field of the file header.
+    pub fn duration(&self) -> u16 {
+        self.frame.duration_or(self.speed)
+    }
+
+    /// Number of chunks in this frame. Uses the old 16-bit field when the new one is 0.
+    pub fn chunk_count(&self) -> u32 {
+        self.frame.chunk_count()
     }
 
     pub fn chunks(&self) -> ChunkIterator<'a> {
         ChunkIterator {
             ptr: self.rest,
-            remaining: self.frame.num_chunks as usize,
+            remaining: self.chunk_count() as usize,
+            offset: self.offset + FRAME_HEADER_SIZE,
+            frame: self.index,
+            index: 0,
+            flags: self.flags,
         }
     }
 }
 
-#[derive(Debug, FromBytes, KnownLayout, Immutable)]
-#[repr(packed)]
+/// The 16 byte header in front of every frame. All multi-byte fields are stored little-endian.
+#[derive(Debug, FromBytes, KnownLayout, Immutable, Unaligned)]
+#[repr(C)]
 pub struct ASEFrameHeader {
-    num_bytes: u32,
-    _magic: u16,
-    old_unused: u16,
-    duration: u16,
+    num_bytes: U32<LE>,
+    magic: U16<LE>,
+    old_unused: U16<LE>,
+    duration: U16<LE>,
     _reserved: [u8; 2],
-    num_chunks: u32,
+    num_chunks: U32<LE>,
 }
 
-#[derive(Error, Debug)]
-pub enum FrameParseError {
-    #[error("Cast error")]
-    CastError,
-    #[error("Invalid magic number: {0}")]
-    InvalidMagic(u16),
+impl ASEFrameHeader {
+    /// Size of the frame in bytes, including this header
+    pub fn num_bytes(&self) -> u32 {
+        self.num_bytes.get()
+    }
+
+    /// Old chunk count field, 0xFFFF when there are more chunks than it can hold
+    pub fn old_num_chunks(&self) -> u16 {
+        self.old_unused.get()
+    }
+
+    /// Raw frame duration in milliseconds, 0 when unset. See [`FrameReader::duration`].
+    pub fn duration(&self) -> u16 {
+        self.duration.get()
+    }
+
+    /// New chunk count field, 0 when unset. See [`FrameReader::chunk_count`].
+    pub fn num_chunks(&self) -> u32 {
+        self.num_chunks.get()
+    }
+
+    /// Frame duration in milliseconds, or `speed` when the frame doesn't set one
+    pub fn duration_or(&self, speed: u16) -> u16 {
+        match self.duration() {
+            0 => speed,
+            duration => duration,
+        }
+    }
+
+    /// Number of chunks, falling back to the old field when the new one is 0
+    pub fn chunk_count(&self) -> u32 {
+        match self.num_chunks() {
+            0 => self.old_num_chunks() as u32,
+            count => count,
+        }
+    }
 }
 
-pub fn parse_frame<'a>(input: &'a [u8]) -> Result<(&'a ASEFrameHeader, &'a [u8]), FrameParseError> {
-    let (frame, rest) = ASEFrameHeader::ref_from_prefix(&input)
-        .map_err(|_| FrameParseError::CastError)?;
-    if frame._magic != 0xF1FA {
-        return Err(FrameParseError::InvalidMagic(frame._magic));
+pub fn parse_frame(input: &[u8]) -> Result<(&ASEFrameHeader, &[u8]), Error> {
+    let (frame, rest) = ref_prefix::<ASEFrameHeader>(input)?;
+    let magic = frame.magic.get();
+    if magic != FRAME_MAGIC {
+        return Err(Error::new(ErrorKind::BadMagic { expected: FRAME_MAGIC, found: magic }, 4));
     }
 
     Ok((frame, rest))
@@ -81,19 +176,73 @@ pub fn parse_frame<'a>(input: &'a [u8]) -> Result<(&'a ASEFrameHeader, &'a [u8])
 
 #[cfg(test)]
 mod test {
-    use crate::parser::{HeaderReader, chunk::ASEChunk};
+    use crate::parser::{HeaderReader, chunk::{ASEChunk, CelData}};
+    use crate::test_util::{FileBuilder, chunk, string};
 
     use super::*;
 
 
+    #[test]
+    fn frame_header_fields_are_little_endian() {
+        let bytes = [
+            0x10, 0x01, 0x00, 0x00,
+            0xFA, 0xF1,
+            0x03, 0x00,
+            0xE8, 0x03,
+            0x00, 0x00,
+            0x04, 0x03, 0x02, 0x01,
+        ];
+        let (frame, rest) = parse_frame(&bytes).unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(frame.num_bytes(), 0x110);
+        assert_eq!(frame.old_num_chunks(), 3);
+        assert_eq!(frame.duration(), 1000);
+        assert_eq!(frame.num_chunks(), 0x01020304);
+    }
+
+    #[test]
+    fn frame_metadata() {
+        let v = std::fs::read("tests/anim_idle.ase").unwrap();
+        let r = HeaderReader::new(&v);
+        let frames: Vec<FrameReader<'_>> = r.frames().collect::<Result<_, _>>().unwrap();
+
+        assert_eq!(frames[0].index(), 0);
+        assert_eq!(frames[0].byte_offset(), 128);
+        assert_eq!(frames[0].duration(), 1000);
+        assert_eq!(frames[0].chunk_count(), 20);
+        assert_eq!(frames[2].index(), 2);
+        assert_eq!(frames[2].byte_offset(), 2731);
+        assert_eq!(frames[2].duration(), 200);
+        assert_eq!(frames[11].chunk_count(), 5);
+    }
+
+    #[test]
+    fn legacy_frame_fields() {
+        let layer = chunk(0x2004, &[&[0u8; 16][..], &string("a")].concat());
+        let data = FileBuilder::new()
+            .speed(150)
+            .frame_with_counts(0, 2, 0, &[layer.clone(), layer.clone()])
+            .frame_with_counts(40, 0xFFFF, 1, &[layer])
+            .build();
+        let r = HeaderReader::new(&data);
+        let frames: Vec<FrameReader<'_>> = r.frames().collect::<Result<_, _>>().unwrap();
+
+        assert_eq!(frames[0].duration(), 150);
+        assert_eq!(frames[0].chunk_count(), 2);
+        assert_eq!(frames[0].chunks().filter(Result::is_ok).count(), 2);
+        assert_eq!(frames[1].duration(), 40);
+        assert_eq!(frames[1].chunk_count(), 1);
+        assert_eq!(frames[1].chunks().filter(Result::is_ok).count(), 1);
+    }
+
     #[test]
     fn num_of_frames_matches() {
         let v = std::fs::read("tests/anim_idle.ase").unwrap();
         let data: &[u8] = &v;
 
         let r = HeaderReader::new(data);
-        let expected_num_frames = r.header().frames;
-        let frames: Vec<FrameReader<'_>> = r.frames().collect();
+        let expected_num_frames = r.header().frames();
+        let frames: Vec<FrameReader<'_>> = r.frames().collect::<Result<_, _>>().unwrap();
 
         assert_eq!(expected_num_frames, frames.len() as _);
     }
@@ -105,11 +254,10 @@ mod test {
         let data: &[u8] = &v;
 
         let r = HeaderReader::new(data);
-        let expected_num_frames = r.header().frames;
-        let frames: Vec<FrameReader<'_>> = r.frames().collect();
+        let frames: Vec<FrameReader<'_>> = r.frames().collect::<Result<_, _>>().unwrap();
 
         for f in frames {
-            let expected_chunks = f.frame.num_chunks;
+            let expected_chunks = f.chunk_count();
             let chunks: Vec<_> =  f.chunks().collect();
             assert_eq!(expected_chunks, chunks.len() as _);
         }
@@ -122,25 +270,25 @@ mod test {
 
         let r = HeaderReader::new(data);
         let mut frames = r.frames();
-        let frame_1 = frames.next().unwrap();
-        let frame_2 = frames.next().unwrap();
-        let frame_3 = frames.next().unwrap();
-        let frame_4 = frames.next().unwrap();
-        let frame_5 = frames.next().unwrap();
-        let frame_6 = frames.next().unwrap();
-        let frame_7 = frames.next().unwrap();
-        let frame_8 = frames.next().unwrap();
-        let frame_9 = frames.next().unwrap();
-        let mut chunks = frame_1.chunks();
+        let frame_1 = frames.next().unwrap().unwrap();
+        let _frame_2 = frames.next().unwrap();
+        let _frame_3 = frames.next().unwrap();
+        let _frame_4 = frames.next().unwrap();
+        let _frame_5 = frames.next().unwrap();
+        let _frame_6 = frames.next().unwrap();
+        let _frame_7 = frames.next().unwrap();
+        let _frame_8 = frames.next().unwrap();
+        let _frame_9 = frames.next().unwrap();
+        let chunks = frame_1.chunks();
 
+        let mut layers = Vec::new();
         for chunk in chunks {
-            // println!("{:?}", chunk);
-            if let ASEChunk::Cel(cel) = chunk {
-                let cel_data = cel.get();
-                println!("{:?}", cel_data);
-            } else {
-                println!("{:x?}", chunk);
+            if let ASEChunk::Cel(cel) = chunk.unwrap() {
+                let Ok(CelData::Raw(image)) = cel.get() else { panic!("expected a raw image cel") };
+                assert_eq!(image.ptr.len(), image.header.width() as usize * image.header.height() as usize);
+                layers.push(cel.cel_header.layer_index());
             }
         }
+        assert_eq!(layers, [0, 1, 2, 3, 4, 6]);
     }
 }
\ No newline at end of file
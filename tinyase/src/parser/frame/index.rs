@@ -0,0 +1,118 @@
+#[cfg(feature = "alloc")]
+use alloc::{vec, vec::Vec};
+
+use crate::error::{Error, ErrorKind};
+use crate::parser::{HEADER_SIZE, HeaderFlags, HeaderReader, frame::FrameReader};
+
+/// Random access to frames, built with a single pass over the file.
+///
+/// The frame offsets are kept in `S`, which can be a caller provided `&mut [u32]`,
+/// an array (see [`FrameIndex::build_array`]) or, with the `alloc` feature, a `Vec<u32>`.
+pub struct FrameIndex<'a, S> {
+    /// Frame data of the file, starting right after the file header
+    data: &'a [u8],
+    /// Offset of each frame from the start of the file
+    offsets: S,
+    len: u16,
+    speed: u16,
+    flags: HeaderFlags,
+}
+
+impl<'a, S> FrameIndex<'a, S>
+where
+    S: AsRef<[u32]> + AsMut<[u32]>,
+{
+    /// Walks every frame header of `reader` once and records its offset in `storage`.
+    ///
+    /// Fails with [`ErrorKind::BufferTooSmall`] if `storage` can't hold one entry per frame,
+    /// or with the first error found while walking the frames.
+    pub fn build(reader: &HeaderReader<'a>, mut storage: S) -> Result<Self, Error> {
+        let len = reader.header().frames();
+        let slots = storage.as_mut();
+        if slots.len() < len as usize {
+            return Err(Error::new(ErrorKind::BufferTooSmall { needed: len as usize, available: slots.len() }, 0));
+        }
+        for (slot, frame) in slots.iter_mut().zip(reader.frames()) {
+            *slot = frame?.byte_offset() as u32;
+        }
+        Ok(FrameIndex { data: reader.rest, offsets: storage, len, speed: reader.header().speed(), flags: reader.header().flags() })
+    }
+
+    /// Number of frames in the index
+    pub fn len(&self) -> usize {
+        self.len as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns frame `index` without walking the frames before it, or `None` if out of range.
+    pub fn frame(&self, index: u16) -> Result<Option<FrameReader<'a>>, Error> {
+        if index >= self.len {
+            return Ok(None);
+        }
+        let offset = self.offsets.as_ref()[index as usize] as usize;
+        let data = &self.data[offset - HEADER_SIZE..];
+        FrameReader::new(data, offset, index, self.speed, self.flags)
+            .map(Some)
+            .map_err(|e| e.offset_by(offset).in_frame(index))
+    }
+}
+
+impl<'a, const N: usize> FrameIndex<'a, [u32; N]> {
+    /// Builds an index in an inline array, for files with at most `N` frames.
+    pub fn build_array(reader: &HeaderReader<'a>) -> Result<Self, Error> {
+        Self::build(reader, [0; N])
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<'a> FrameIndex<'a, Vec<u32>> {
+    /// Builds an index in a heap allocated vector sized to the frame count.
+    pub fn build_vec(reader: &HeaderReader<'a>) -> Result<Self, Error> {
+        Self::build(reader, vec![0; reader.header().frames() as usize])
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn assert_matches_iteration<S: AsRef<[u32]> + AsMut<[u32]>>(reader: &HeaderReader<'_>, index: &FrameIndex<'_, S>) {
+        assert_eq!(index.len(), reader.header().frames() as usize);
+        for frame in reader.frames() {
+            let frame = frame.unwrap();
+            let indexed = index.frame(frame.index()).unwrap().unwrap();
+            assert_eq!(indexed.byte_offset(), frame.byte_offset());
+            assert_eq!(indexed.duration(), frame.duration());
+            assert_eq!(indexed.chunks().count(), frame.chunks().count());
+        }
+        assert!(index.frame(reader.header().frames()).unwrap().is_none());
+    }
+
+    #[test]
+    fn frame_index_storage() {
+        let v = std::fs::read("tests/anim_idle.ase").unwrap();
+        let r = HeaderReader::new(&v);
+
+        let mut slots = [0u32; 16];
+        let index = FrameIndex::build(&r, &mut slots[..]).unwrap();
+        assert_matches_iteration(&r, &index);
+
+        let index = FrameIndex::<[u32; 12]>::build_array(&r).unwrap();
+        assert_matches_iteration(&r, &index);
+        assert_eq!(index.frame(11).unwrap().unwrap().index(), 11);
+
+        #[cfg(feature = "alloc")]
+        assert_matches_iteration(&r, &FrameIndex::build_vec(&r).unwrap());
+    }
+
+    #[test]
+    fn frame_index_capacity() {
+        let v = std::fs::read("tests/anim_idle.ase").unwrap();
+        let r = HeaderReader::new(&v);
+        let err = FrameIndex::<[u32; 4]>::build_array(&r).err().unwrap();
+        assert_eq!(err.kind, ErrorKind::BufferTooSmall { needed: 12, available: 4 });
+    }
+}
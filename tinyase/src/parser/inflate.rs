@@ -0,0 +1,200 @@
+//! zlib decompression of compressed images, without allocating.
+
+use miniz_oxide::inflate::TINFLStatus;
+use miniz_oxide::inflate::core::{DecompressorOxide, decompress, inflate_flags};
+
+use crate::error::{Error, ErrorKind};
+
+/// Inflates the zlib stream `input` into `out`, which it must fill exactly.
+///
+/// The decompressor state takes about 11 KiB of stack. Error offsets are relative to
+/// the start of `input`.
+pub(crate) fn inflate_exact(input: &[u8], out: &mut [u8]) -> Result<(), Error> {
+    let mut state = DecompressorOxide::new();
+    let flags = inflate_flags::TINFL_FLAG_PARSE_ZLIB_HEADER
+        | inflate_flags::TINFL_FLAG_COMPUTE_ADLER32
+        | inflate_flags::TINFL_FLAG_USING_NON_WRAPPING_OUTPUT_BUF;
+    let (status, read, written) = decompress(&mut state, input, out, 0, flags);
+    match status {
+        TINFLStatus::Done if written == out.len() => Ok(()),
+        TINFLStatus::Done | TINFLStatus::HasMoreOutput => {
+            Err(Error::new(ErrorKind::ImageSizeMismatch { expected: out.len() }, read))
+        }
+        _ => Err(Error::new(ErrorKind::InvalidCompressedData, read)),
+    }
+}
+
+/// Inflates a zlib stream a few bytes at a time, keeping only the last `window` bytes
+/// of output around for back references.
+///
+/// A stream can refer back as far as the window declared in its zlib header, or the
+/// whole output when that is smaller, so the caller's window must cover that much.
+pub(crate) struct StreamInflater<'a, 'w> {
+    state: DecompressorOxide,
+    input: &'a [u8],
+    read: usize,
+    window: &'w mut [u8],
+    /// Next write position in `window`
+    pos: usize,
+    /// Decompressed bytes in `window` not handed out yet, ending at `pos`
+    pending: usize,
+    done: bool,
+    /// Total size the stream must decompress to, for errors
+    expected: usize,
+    /// Running adler32 of the output, checked against the zlib trailer
+    adler: (u32, u32),
+}
+
+impl<'a, 'w> StreamInflater<'a, 'w> {
+    /// Checks that `window` is large enough for `input`, which must decompress to `expected` bytes.
+    /// Only the largest power of two prefix of `window` is used.
+    pub fn new(input: &'a [u8], expected: usize, window: &'w mut [u8]) -> Result<Self, Error> {
+        // miniz rejects windows smaller than the declared one, so the header is read here
+        let &[cmf, flg, ..] = input else {
+            return Err(Error::new(ErrorKind::InvalidCompressedData, 0));
+        };
+        if cmf & 0x0F != 8 || cmf >> 4 > 7 || flg & 0x20 != 0 || !(cmf as u16 * 256 + flg as u16).is_multiple_of(31) {
+            return Err(Error::new(ErrorKind::InvalidCompressedData, 0));
+        }
+        let declared = 1usize << ((cmf >> 4) as u32 + 8);
+        let needed = expected.max(1).checked_next_power_of_two().map_or(declared, |len| declared.min(len));
+        let size = match window.len() {
+            0 => 0,
+            len => 1 << (usize::BITS - 1 - len.leading_zeros()),
+        };
+        if size < needed {
+            return Err(Error::new(ErrorKind::BufferTooSmall { needed, available: window.len() }, 0));
+        }
+        Ok(StreamInflater {
+            state: DecompressorOxide::new(),
+            input,
+            read: 2,
+            window: &mut window[..size],
+            pos: 0,
+            pending: 0,
+            done: false,
+            expected,
+            adler: (1, 0),
+        })
+    }
+
+    /// Fills `out` with the next decompressed bytes.
+    pub fn read_exact(&mut self, out: &mut [u8]) -> Result<(), Error> {
+        let mut filled = 0;
+        while filled < out.len() {
+            if self.pending == 0 {
+                self.fill()?;
+                if self.pending == 0 {
+                    return Err(self.mismatch());
+                }
+            }
+            let mask = self.window.len() - 1;
+            let start = self.pos.wrapping_sub(self.pending) & mask;
+            // Pending bytes never wrap, each fill stops at the end of the window
+            let len = self.pending.min(out.len() - filled);
+            out[filled..filled + len].copy_from_slice(&self.window[start..start + len]);
+            self.pending -= len;
+            filled += len;
+        }
+        Ok(())
+    }
+
+    /// Checks that the stream ends here, with a valid checksum.
+    pub fn finish(&mut self) -> Result<(), Error> {
+        if self.pending == 0 {
+            self.fill()?;
+        }
+        match self.pending == 0 && self.done {
+            true => Ok(()),
+            false => Err(self.mismatch()),
+        }
+    }
+
+    fn fill(&mut self) -> Result<(), Error> {
+        if self.done {
+            return Ok(());
+        }
+        let (status, read, written) = decompress(&mut self.state, &self.input[self.read..], self.window, self.pos, 0);
+        self.read += read;
+        for &b in &self.window[self.pos..self.pos + written] {
+            self.adler.0 = (self.adler.0 + b as u32) % 65521;
+            self.adler.1 = (self.adler.1 + self.adler.0) % 65521;
+        }
+        self.pending = written;
+        self.pos = (self.pos + written) & (self.window.len() - 1);
+        match status {
+            TINFLStatus::Done => {
+                let trailer = self.input.get(self.read..self.read + 4)
+                    .ok_or(Error::new(ErrorKind::InvalidCompressedData, self.read))?;
+                if u32::from_be_bytes([trailer[0], trailer[1], trailer[2], trailer[3]]) != self.adler.1 << 16 | self.adler.0 {
+                    return Err(Error::new(ErrorKind::InvalidCompressedData, self.read));
+                }
+                self.done = true;
+            }
+            TINFLStatus::HasMoreOutput => {}
+            _ => return Err(Error::new(ErrorKind::InvalidCompressedData, self.read)),
+        }
+        Ok(())
+    }
+
+    fn mismatch(&self) -> Error {
+        Error::new(ErrorKind::ImageSizeMismatch { expected: self.expected }, self.read)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::zlib;
+
+    #[test]
+    fn inflate() {
+        let data: std::vec::Vec<u8> = (0..=255).cycle().take(1000).collect();
+        let compressed = zlib(&data);
+        let mut out = [0u8; 1000];
+        inflate_exact(&compressed, &mut out).unwrap();
+        assert_eq!(out[..], data[..]);
+
+        let mut short = [0u8; 999];
+        assert_eq!(inflate_exact(&compressed, &mut short).unwrap_err().kind, ErrorKind::ImageSizeMismatch { expected: 999 });
+        let mut long = [0u8; 1001];
+        assert_eq!(inflate_exact(&compressed, &mut long).unwrap_err().kind, ErrorKind::ImageSizeMismatch { expected: 1001 });
+
+        assert_eq!(inflate_exact(&compressed[..compressed.len() - 3], &mut out).unwrap_err().kind, ErrorKind::InvalidCompressedData);
+        let mut corrupt = compressed.clone();
+        let last = corrupt.len() - 1;
+        corrupt[last] ^= 1;
+        assert_eq!(inflate_exact(&corrupt, &mut out).unwrap_err().kind, ErrorKind::InvalidCompressedData);
+    }
+
+    #[test]
+    fn stream_through_a_window() {
+        let data: std::vec::Vec<u8> = (0..5000u32).map(|i| (i * i % 251) as u8).collect();
+        let compressed = zlib(&data);
+        let mut window = [0u8; 8192 + 100];
+        let mut inflater = StreamInflater::new(&compressed, 5000, &mut window).unwrap();
+        for chunk in data.chunks(300) {
+            let mut out = [0u8; 300];
+            inflater.read_exact(&mut out[..chunk.len()]).unwrap();
+            assert_eq!(&out[..chunk.len()], chunk);
+        }
+        inflater.finish().unwrap();
+
+        let mut small = [0u8; 4096];
+        let e = StreamInflater::new(&compressed, 5000, &mut small).err().unwrap();
+        assert_eq!(e.kind, ErrorKind::BufferTooSmall { needed: 8192, available: 4096 });
+
+        let mut inflater = StreamInflater::new(&compressed, 4000, &mut window).unwrap();
+        let mut out = [0u8; 4000];
+        inflater.read_exact(&mut out).unwrap();
+        assert_eq!(inflater.finish().unwrap_err().kind, ErrorKind::ImageSizeMismatch { expected: 4000 });
+
+        let mut corrupt = compressed.clone();
+        let last = corrupt.len() - 1;
+        corrupt[last] ^= 1;
+        let mut inflater = StreamInflater::new(&corrupt, 5000, &mut window).unwrap();
+        let mut out = [0u8; 5000];
+        let e = inflater.read_exact(&mut out).and_then(|_| inflater.finish()).unwrap_err();
+        assert_eq!(e.kind, ErrorKind::InvalidCompressedData);
+    }
+}
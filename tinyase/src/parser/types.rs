@@ -0,0 +1,105 @@
+//! Small value types shared by several chunks.
+
+/// A 16.16 fixed point number, the spec's `FIXED`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Fixed(i32);
+
+impl Fixed {
+    pub const ONE: Fixed = Fixed(1 << 16);
+
+    pub const fn from_bits(bits: i32) -> Self {
+        Fixed(bits)
+    }
+
+    pub const fn to_bits(self) -> i32 {
+        self.0
+    }
+
+    pub fn to_f32(self) -> f32 {
+        self.0 as f32 / 65536.0
+    }
+
+    pub fn to_f64(self) -> f64 {
+        self.0 as f64 / 65536.0
+    }
+
+    pub const fn from_int(value: i16) -> Self {
+        Fixed((value as i32) << 16)
+    }
+
+    /// Largest integer less than or equal to this number
+    pub const fn floor(self) -> i32 {
+        self.0 >> 16
+    }
+
+    /// Smallest integer greater than or equal to this number
+    pub const fn ceil(self) -> i32 {
+        ((self.0 as i64 + 0xFFFF) >> 16) as i32
+    }
+
+    /// Nearest integer, halves are rounded up
+    pub const fn round(self) -> i32 {
+        ((self.0 as i64 + 0x8000) >> 16) as i32
+    }
+
+    /// The fractional part, always positive: `floor() + fract() == self`
+    pub const fn fract(self) -> Fixed {
+        Fixed(self.0 & 0xFFFF)
+    }
+}
+
+impl From<i16> for Fixed {
+    fn from(value: i16) -> Self {
+        Fixed::from_int(value)
+    }
+}
+
+impl From<Fixed> for f32 {
+    fn from(value: Fixed) -> Self {
+        value.to_f32()
+    }
+}
+
+impl From<Fixed> for f64 {
+    fn from(value: Fixed) -> Self {
+        value.to_f64()
+    }
+}
+
+/// The spec's `POINT`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Point {
+    pub x: i32,
+    pub y: i32,
+}
+
+/// The spec's `SIZE`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Size {
+    pub width: i32,
+    pub height: i32,
+}
+
+/// The spec's `RECT`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Rect {
+    pub origin: Point,
+    pub size: Size,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn fixed_conversions() {
+        let f = Fixed::from_bits(-0x0001_4000);
+        assert_eq!(f.to_f32(), -1.25);
+        assert_eq!((f.floor(), f.ceil(), f.round()), (-2, -1, -1));
+        assert_eq!(f.fract(), Fixed::from_bits(0xC000));
+        assert_eq!(Fixed::from(3i16), Fixed::from_bits(3 << 16));
+        assert_eq!(Fixed::from_bits(0x0002_8000).round(), 3);
+        assert_eq!(Fixed::from_bits(i32::MAX).ceil(), 32768);
+        assert_eq!(f64::from(Fixed::ONE), 1.0);
+    }
+}
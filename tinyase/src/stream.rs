@@ -0,0 +1,406 @@
+//! Parser over an [`embedded_io`] `Read + Seek` source, for files that don't fit in RAM.
+//!
+//! Only the file header is kept in memory. Frame and chunk headers are read on demand
+//! and chunk payloads are copied into a caller supplied scratch buffer, then decoded
+//! with the same code as the slice based [`HeaderReader`](crate::parser::HeaderReader).
+
+use embedded_io::{Read, ReadExactError, Seek, SeekFrom};
+use thiserror::Error;
+use zerocopy::*;
+
+use crate::error::{Error as ParseError, ErrorKind};
+use crate::parser::{
+    ASEHeader, ColorDepth, FRAME_HEADER_SIZE, HEADER_SIZE,
+    chunk::{ASEChunk, ASEChunkHeader, CHUNK_HEADER_SIZE},
+    frame::{ASEFrameHeader, parse_frame},
+};
+
+#[derive(Error, Debug)]
+pub enum StreamError<E: core::fmt::Debug> {
+    #[error("I/O error: {0:?}")]
+    Io(E),
+    #[error(transparent)]
+    Parse(#[from] ParseError),
+}
+
+pub struct StreamReader<R> {
+    reader: R,
+    header: ASEHeader,
+    depth: ColorDepth,
+}
+
+impl<R: Read + Seek> StreamReader<R> {
+    /// Reads and validates the file header, see [`HeaderReader::try_new`](crate::parser::HeaderReader::try_new).
+    ///
+    /// The stream is expected to start at the beginning of the file and its length
+    /// is determined by seeking to the end.
+    pub fn new(mut reader: R) -> Result<Self, StreamError<R::Error>> {
+        let available = reader.seek(SeekFrom::End(0)).map_err(StreamError::Io)?;
+        let mut buf = [0u8; HEADER_SIZE];
+        read_exact_at(&mut reader, 0, &mut buf)?;
+        let header = ASEHeader::read_from_bytes(&buf)
+            .map_err(|_| ParseError::truncated(0, HEADER_SIZE, 0))?;
+        header.validate(available as usize)?;
+        let depth = header.color_depth()?;
+        Ok(StreamReader { reader, header, depth })
+    }
+
+    pub fn header(&self) -> &ASEHeader {
+        &self.header
+    }
+
+    pub fn color_depth(&self) -> ColorDepth {
+        self.depth
+    }
+
+    /// Gives the underlying stream back.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    /// Cursor over the frames of the file, advanced with [`StreamFrames::next`].
+    pub fn frames(&self) -> StreamFrames {
+        StreamFrames {
+            offset: HEADER_SIZE as u64,
+            index: 0,
+            remaining: self.header.frames(),
+        }
+    }
+
+    /// Cursor over the chunks of `frame`, advanced with [`StreamChunks::next`].
+    pub fn chunks(&self, frame: &StreamFrame) -> StreamChunks {
+        StreamChunks {
+            offset: frame.offset + FRAME_HEADER_SIZE as u64,
+            end: frame.offset + frame.header.num_bytes() as u64,
+            frame: frame.index,
+            index: 0,
+            remaining: frame.chunk_count(),
+        }
+    }
+
+    /// Reads the payload of `chunk` into `scratch` and decodes it.
+    ///
+    /// Fails with [`ErrorKind::BufferTooSmall`] if the payload doesn't fit in `scratch`.
+    pub fn read_chunk<'b>(&mut self, chunk: &StreamChunk, scratch: &'b mut [u8]) -> Result<ASEChunk<'b>, StreamError<R::Error>> {
+        let data = self.read_chunk_data(chunk, scratch)?;
+        ASEChunk::new(chunk.chunk_type(), data, self.header.flags()).map_err(|e| chunk.locate(e).into())
+    }
+
+    /// Reads the raw payload of `chunk` into `scratch`, without decoding it.
+    pub fn read_chunk_data<'b>(&mut self, chunk: &StreamChunk, scratch: &'b mut [u8]) -> Result<&'b [u8], StreamError<R::Error>> {
+        let len = chunk.payload_len();
+        if len > scratch.len() {
+            let e = ParseError::new(ErrorKind::BufferTooSmall { needed: len, available: scratch.len() }, 0);
+            return Err(chunk.locate(e).into());
+        }
+        let data = &mut scratch[..len];
+        read_exact_at(&mut self.reader, chunk.offset + CHUNK_HEADER_SIZE as u64, data)
+            .map_err(|e| match e {
+                StreamError::Parse(e) => StreamError::Parse(chunk.locate(e)),
+                e => e,
+            })?;
+        Ok(data)
+    }
+}
+
+/// Frame cursor, see [`StreamReader::frames`].
+#[derive(Debug, Clone)]
+pub struct StreamFrames {
+    offset: u64,
+    index: u16,
+    remaining: u16,
+}
+
+impl StreamFrames {
+    /// Reads the next frame header, or returns `None` after the last frame.
+    /// A frame header that cannot be read ends the iteration after the error.
+    pub fn next<R: Read + Seek>(&mut self, reader: &mut StreamReader<R>) -> Result<Option<StreamFrame>, StreamError<R::Error>> {
+        if self.remaining == 0 {
+            return Ok(None);
+        }
+        let frame = self.read(reader).map_err(|e| {
+            self.remaining = 0;
+            match e {
+                StreamError::Parse(e) => StreamError::Parse(e.offset_by(self.offset as usize).in_frame(self.index)),
+                e => e,
+            }
+        })?;
+        self.offset += frame.header.num_bytes() as u64;
+        self.index += 1;
+        self.remaining -= 1;
+        Ok(Some(frame))
+    }
+
+    /// Reads the frame header at the cursor. Error offsets are relative to the frame.
+    fn read<R: Read + Seek>(&self, reader: &mut StreamReader<R>) -> Result<StreamFrame, StreamError<R::Error>> {
+        let mut buf = [0u8; FRAME_HEADER_SIZE];
+        read_exact_at(&mut reader.reader, self.offset, &mut buf)?;
+        let (header, _) = parse_frame(&buf)?;
+        let size = header.num_bytes();
+        if (size as usize) < FRAME_HEADER_SIZE {
+            return Err(ParseError::new(ErrorKind::InvalidFrameSize(size), 0).into());
+        }
+        let available = reader.header.filesize() as u64 - self.offset;
+        if size as u64 > available {
+            return Err(ParseError::truncated(0, size as usize, available as usize).into());
+        }
+        Ok(StreamFrame {
+            header: ASEFrameHeader::read_from_bytes(&buf).map_err(|_| ParseError::truncated(0, FRAME_HEADER_SIZE, 0))?,
+            offset: self.offset,
+            index: self.index,
+            speed: reader.header.speed(),
+        })
+    }
+}
+
+/// A frame header read from the stream.
+#[derive(Debug)]
+pub struct StreamFrame {
+    header: ASEFrameHeader,
+    offset: u64,
+    index: u16,
+    speed: u16,
+}
+
+impl StreamFrame {
+    pub fn header(&self) -> &ASEFrameHeader {
+        &self.header
+    }
+
+    /// Index of this frame, starting at 0
+    pub fn index(&self) -> u16 {
+        self.index
+    }
+
+    /// Offset of the frame header from the start of the file
+    pub fn byte_offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Frame duration in milliseconds, see [`FrameReader::duration`](crate::parser::frame::FrameReader::duration).
+    pub fn duration(&self) -> u16 {
+        self.header.duration_or(self.speed)
+    }
+
+    /// Number of chunks, see [`FrameReader::chunk_count`](crate::parser::frame::FrameReader::chunk_count).
+    pub fn chunk_count(&self) -> u32 {
+        self.header.chunk_count()
+    }
+}
+
+/// Chunk cursor, see [`StreamReader::chunks`].
+#[derive(Debug, Clone)]
+pub struct StreamChunks {
+    offset: u64,
+    end: u64,
+    frame: u16,
+    index: u32,
+    remaining: u32,
+}
+
+impl StreamChunks {
+    /// Reads the next chunk header, or returns `None` after the last chunk of the frame.
+    /// A chunk header that cannot be read ends the iteration after the error.
+    pub fn next<R: Read + Seek>(&mut self, reader: &mut StreamReader<R>) -> Result<Option<StreamChunk>, StreamError<R::Error>> {
+        if self.remaining == 0 {
+            return Ok(None);
+        }
+        let chunk = self.read(reader).map_err(|e| {
+            self.remaining = 0;
+            match e {
+                StreamError::Parse(e) => StreamError::Parse(e.offset_by(self.offset as usize).in_chunk_index(self.index).in_frame(self.frame)),
+                e => e,
+            }
+        })?;
+        self.offset += chunk.header.size() as u64;
+        self.index += 1;
+        self.remaining -= 1;
+        Ok(Some(chunk))
+    }
+
+    /// Reads the chunk header at the cursor. Error offsets are relative to the chunk.
+    fn read<R: Read + Seek>(&self, reader: &mut StreamReader<R>) -> Result<StreamChunk, StreamError<R::Error>> {
+        let available = self.end - self.offset;
+        let mut buf = [0u8; CHUNK_HEADER_SIZE];
+        if available < CHUNK_HEADER_SIZE as u64 {
+            return Err(ParseError::truncated(0, CHUNK_HEADER_SIZE, available as usize).into());
+        }
+        read_exact_at(&mut reader.reader, self.offset, &mut buf)?;
+        let header = ASEChunkHeader::read_from_bytes(&buf)
+            .map_err(|_| ParseError::truncated(0, CHUNK_HEADER_SIZE, 0))?;
+        let size = header.size();
+        if (size as usize) < CHUNK_HEADER_SIZE {
+            return Err(ParseError::new(ErrorKind::InvalidChunkSize(size), 0).into());
+        }
+        if size as u64 > available {
+            return Err(ParseError::new(ErrorKind::TruncatedChunk { declared: size, available: available as usize }, 0).into());
+        }
+        Ok(StreamChunk { header, offset: self.offset, frame: self.frame, index: self.index })
+    }
+}
+
+/// A chunk header read from the stream. The payload is read with [`StreamReader::read_chunk`].
+#[derive(Debug)]
+pub struct StreamChunk {
+    header: ASEChunkHeader,
+    offset: u64,
+    frame: u16,
+    index: u32,
+}
+
+impl StreamChunk {
+    pub fn chunk_type(&self) -> u16 {
+        self.header.chunk_type()
+    }
+
+    /// Offset of the chunk header from the start of the file
+    pub fn byte_offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Index of the chunk within its frame
+    pub fn index(&self) -> u32 {
+        self.index
+    }
+
+    /// Size of the payload, the scratch space needed by [`StreamReader::read_chunk`]
+    pub fn payload_len(&self) -> usize {
+        self.header.size() as usize - CHUNK_HEADER_SIZE
+    }
+
+    /// Turns an error relative to the payload into one located in the file.
+    fn locate(&self, e: ParseError) -> ParseError {
+        e.offset_by(self.offset as usize + CHUNK_HEADER_SIZE)
+            .in_chunk(self.index, self.chunk_type())
+            .in_frame(self.frame)
+    }
+}
+
+/// Reads exactly `buf.len()` bytes at `offset`. A short read is reported as truncated data,
+/// with an error offset relative to `offset`.
+fn read_exact_at<R: Read + Seek>(reader: &mut R, offset: u64, buf: &mut [u8]) -> Result<(), StreamError<R::Error>> {
+    reader.seek(SeekFrom::Start(offset)).map_err(StreamError::Io)?;
+    reader.read_exact(buf).map_err(|e| match e {
+        ReadExactError::UnexpectedEof => ParseError::truncated(0, buf.len(), 0).into(),
+        ReadExactError::Other(e) => StreamError::Io(e),
+    })
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use super::*;
+    use crate::parser::{HeaderReader, chunk::CelData};
+
+    /// In-memory `Read + Seek` source.
+    pub struct Cursor<'a> {
+        data: &'a [u8],
+        pos: u64,
+    }
+
+    impl<'a> Cursor<'a> {
+        pub fn new(data: &'a [u8]) -> Self {
+            Cursor { data, pos: 0 }
+        }
+    }
+
+    impl embedded_io::ErrorType for Cursor<'_> {
+        type Error = embedded_io::ErrorKind;
+    }
+
+    impl Read for Cursor<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+            let rest = self.data.get(self.pos as usize..).unwrap_or(&[]);
+            let n = rest.len().min(buf.len());
+            buf[..n].copy_from_slice(&rest[..n]);
+            self.pos += n as u64;
+            Ok(n)
+        }
+    }
+
+    impl Seek for Cursor<'_> {
+        fn seek(&mut self, pos: SeekFrom) -> Result<u64, Self::Error> {
+            let pos = match pos {
+                SeekFrom::Start(p) => Some(p),
+                SeekFrom::End(d) => (self.data.len() as u64).checked_add_signed(d),
+                SeekFrom::Current(d) => self.pos.checked_add_signed(d),
+            };
+            self.pos = pos.ok_or(embedded_io::ErrorKind::InvalidInput)?;
+            Ok(self.pos)
+        }
+    }
+
+    #[test]
+    fn stream_matches_slice_reader() {
+        let v = std::fs::read("tests/anim_idle.ase").unwrap();
+        let slice_reader = HeaderReader::new(&v);
+        let mut stream = StreamReader::new(Cursor::new(&v)).unwrap();
+        assert_eq!(stream.header().frames(), 12);
+        assert_eq!(stream.color_depth(), ColorDepth::Indexed);
+
+        let mut scratch = [0u8; 2048];
+        let mut frames = stream.frames();
+        let mut slice_frames = slice_reader.frames();
+        while let Some(frame) = frames.next(&mut stream).unwrap() {
+            let slice_frame = slice_frames.next().unwrap().unwrap();
+            assert_eq!(frame.byte_offset(), slice_frame.byte_offset() as u64);
+            assert_eq!(frame.duration(), slice_frame.duration());
+            assert_eq!(frame.chunk_count(), slice_frame.chunk_count());
+
+            let mut chunks = stream.chunks(&frame);
+            let mut slice_chunks = slice_frame.chunks();
+            while let Some(chunk) = chunks.next(&mut stream).unwrap() {
+                let expected = slice_chunks.next().unwrap().unwrap();
+                let decoded = stream.read_chunk(&chunk, &mut scratch).unwrap();
+                match (decoded, expected) {
+                    (ASEChunk::Cel(a), ASEChunk::Cel(b)) => {
+                        assert_eq!(a.cel_header.point_x(), b.cel_header.point_x());
+                        match (a.get().unwrap(), b.get().unwrap()) {
+                            (CelData::Raw(a), CelData::Raw(b)) => assert_eq!(a.ptr, b.ptr),
+                            (CelData::Linked(a), CelData::Linked(b)) => assert_eq!(a, b),
+                            (CelData::Compressed(a), CelData::Compressed(b)) => assert_eq!(a.compressed_data(), b.compressed_data()),
+                            (CelData::Tilemap(a), CelData::Tilemap(b)) => assert_eq!(a.compressed_data(), b.compressed_data()),
+                            _ => panic!("cel data differs"),
+                        }
+                    }
+                    (ASEChunk::Layer(a), ASEChunk::Layer(b)) => assert_eq!(a.name, b.name),
+                    (ASEChunk::Unknown(a, da), ASEChunk::Unknown(b, db)) => assert_eq!((a, da), (b, db)),
+                    // only chunks read from a whole frame carry user data
+                    (ASEChunk::Tags(a), ASEChunk::Tags(b)) => {
+                        assert!(a.iter().map(|t| (t.from, t.to, t.name)).eq(b.iter().map(|t| (t.from, t.to, t.name))))
+                    }
+                    (a, b) => assert_eq!(std::format!("{a:?}"), std::format!("{b:?}")),
+                }
+            }
+            assert!(slice_chunks.next().is_none());
+        }
+        assert!(slice_frames.next().is_none());
+    }
+
+    #[test]
+    fn stream_errors() {
+        let v = std::fs::read("tests/anim_idle.ase").unwrap();
+        assert!(matches!(
+            StreamReader::new(Cursor::new(&v[..1000])),
+            Err(StreamError::Parse(ParseError { kind: ErrorKind::TruncatedFile { .. }, .. }))
+        ));
+
+        let mut stream = StreamReader::new(Cursor::new(&v)).unwrap();
+        let frame = stream.frames().next(&mut stream).unwrap().unwrap();
+        let chunk = stream.chunks(&frame).next(&mut stream).unwrap().unwrap();
+        let mut scratch = [0u8; 4];
+        let Err(StreamError::Parse(e)) = stream.read_chunk(&chunk, &mut scratch) else { panic!("expected an error") };
+        assert_eq!(e.kind, ErrorKind::BufferTooSmall { needed: 30, available: 4 });
+        assert_eq!((e.frame, e.chunk, e.chunk_type), (Some(0), Some(0), Some(0x2008)));
+
+        // the frame declares a second chunk whose header is missing
+        let data = crate::test_util::FileBuilder::new()
+            .frame_with_counts(100, 2, 2, &[crate::test_util::chunk(0x2020, &[0; 4])])
+            .build();
+        let mut stream = StreamReader::new(Cursor::new(&data)).unwrap();
+        let frame = stream.frames().next(&mut stream).unwrap().unwrap();
+        let mut chunks = stream.chunks(&frame);
+        assert!(chunks.next(&mut stream).unwrap().is_some());
+        let Err(StreamError::Parse(e)) = chunks.next(&mut stream) else { panic!("expected an error") };
+        assert_eq!((e.frame, e.chunk, e.chunk_type), (Some(0), Some(1), None));
+    }
+}
@@ -0,0 +1,122 @@
+//! Helpers to assemble small aseprite files in memory for tests.
+
+#![allow(dead_code)]
+
+use std::vec::Vec;
+
+/// Serializes a chunk: `<u32 size><u16 type>` followed by `payload`.
+pub fn chunk(chunk_type: u16, payload: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    out.extend_from_slice(&(payload.len() as u32 + 6).to_le_bytes());
+    out.extend_from_slice(&chunk_type.to_le_bytes());
+    out.extend_from_slice(payload);
+    out
+}
+
+/// Serializes a `STRING`.
+pub fn string(s: &str) -> Vec<u8> {
+    let mut out = Vec::new();
+    out.extend_from_slice(&(s.len() as u16).to_le_bytes());
+    out.extend_from_slice(s.as_bytes());
+    out
+}
+
+/// Serializes a palette chunk payload (0x2019), `entries` being already serialized.
+pub fn palette(size: u32, first: u32, last: u32, entries: &[u8]) -> Vec<u8> {
+    [&size.to_le_bytes()[..], &first.to_le_bytes(), &last.to_le_bytes(), &[0; 8], entries].concat()
+}
+
+/// Serializes palette entries without names.
+pub fn palette_colors(colors: &[[u8; 4]]) -> Vec<u8> {
+    colors.iter().flat_map(|c| [&[0, 0][..], c].concat()).collect()
+}
+
+/// Compresses `data` as a zlib stream, like compressed images and tilesets.
+pub fn zlib(data: &[u8]) -> Vec<u8> {
+    miniz_oxide::deflate::compress_to_vec_zlib(data, 6)
+}
+
+/// Serializes an external files chunk payload (0x2008) from `(id, type, name)` entries.
+pub fn external_files(entries: &[(u32, u8, &str)]) -> Vec<u8> {
+    let mut out = [&(entries.len() as u32).to_le_bytes()[..], &[0; 8]].concat();
+    for &(id, kind, name) in entries {
+        out.extend_from_slice(&[&id.to_le_bytes()[..], &[kind], &[0; 7], &string(name)].concat());
+    }
+    out
+}
+
+/// Serializes a tileset chunk payload (0x2023) named "ground" with a base index of 1.
+/// `tail` holds the external file reference and the tile image, as `flags` require.
+pub fn tileset(id: u32, flags: u32, count: u32, width: u16, height: u16, tail: &[u8]) -> Vec<u8> {
+    [
+        &id.to_le_bytes()[..], &flags.to_le_bytes(), &count.to_le_bytes(),
+        &width.to_le_bytes(), &height.to_le_bytes(), &1i16.to_le_bytes(), &[0; 14],
+        &string("ground"), tail,
+    ].concat()
+}
+
+pub struct FileBuilder {
+    width: u16,
+    height: u16,
+    depth: u16,
+    flags: u32,
+    speed: u16,
+    frames: Vec<Vec<u8>>,
+}
+
+impl FileBuilder {
+    pub fn new() -> Self {
+        FileBuilder { width: 16, height: 16, depth: 32, flags: 1, speed: 100, frames: Vec::new() }
+    }
+
+    pub fn depth(mut self, depth: u16) -> Self {
+        self.depth = depth;
+        self
+    }
+
+    pub fn flags(mut self, flags: u32) -> Self {
+        self.flags = flags;
+        self
+    }
+
+    pub fn speed(mut self, speed: u16) -> Self {
+        self.speed = speed;
+        self
+    }
+
+    /// Adds a frame holding `chunks`, each already serialized with [`chunk`].
+    pub fn frame(self, duration: u16, chunks: &[Vec<u8>]) -> Self {
+        let count = chunks.len();
+        self.frame_with_counts(duration, count as u16, count as u32, chunks)
+    }
+
+    /// Adds a frame with explicit old and new chunk count fields.
+    pub fn frame_with_counts(mut self, duration: u16, old: u16, new: u32, chunks: &[Vec<u8>]) -> Self {
+        let body: Vec<u8> = chunks.concat();
+        let mut frame = Vec::new();
+        frame.extend_from_slice(&(body.len() as u32 + 16).to_le_bytes());
+        frame.extend_from_slice(&0xF1FAu16.to_le_bytes());
+        frame.extend_from_slice(&old.to_le_bytes());
+        frame.extend_from_slice(&duration.to_le_bytes());
+        frame.extend_from_slice(&[0, 0]);
+        frame.extend_from_slice(&new.to_le_bytes());
+        frame.extend_from_slice(&body);
+        self.frames.push(frame);
+        self
+    }
+
+    pub fn build(&self) -> Vec<u8> {
+        let body: Vec<u8> = self.frames.concat();
+        let mut out = std::vec![0u8; 128];
+        out[0..4].copy_from_slice(&(body.len() as u32 + 128).to_le_bytes());
+        out[4..6].copy_from_slice(&0xA5E0u16.to_le_bytes());
+        out[6..8].copy_from_slice(&(self.frames.len() as u16).to_le_bytes());
+        out[8..10].copy_from_slice(&self.width.to_le_bytes());
+        out[10..12].copy_from_slice(&self.height.to_le_bytes());
+        out[12..14].copy_from_slice(&self.depth.to_le_bytes());
+        out[14..18].copy_from_slice(&self.flags.to_le_bytes());
+        out[18..20].copy_from_slice(&self.speed.to_le_bytes());
+        out.extend_from_slice(&body);
+        out
+    }
+}
@@ -0,0 +1,257 @@
+//! Single pass, callback based parsing.
+//!
+//! [`walk`] reads a whole file once and reports everything it finds to an [`AseVisitor`].
+//! Chunk types tinyase doesn't know, or wants to be decoded differently, can be handled
+//! by registering a [`ChunkDecoder`] with a [`Walker`].
+
+use crate::error::Error;
+use crate::parser::HeaderReader;
+use crate::parser::chunk::{ASEChunk, CelContainer, RawChunk, cel_extra::CelExtra, color_profile::ColorProfile, external_files::ExternalFiles, layer::Layer, mask::Mask, old_palette::OldPalette, palette::Palette, slice::Slice, tags::Tags, tileset::Tileset, user_data::UserData};
+use crate::parser::frame::FrameReader;
+
+/// Receives the contents of a file from [`walk`], in file order.
+///
+/// Every method has an empty default, so a visitor only implements what it needs.
+#[allow(unused_variables)]
+pub trait AseVisitor<'a> {
+    /// Called once, before the first frame.
+    fn on_header(&mut self, reader: &HeaderReader<'a>) {}
+
+    /// Called before the chunks of `frame`.
+    fn on_frame_start(&mut self, frame: &FrameReader<'a>) {}
+
+    /// Called after the last chunk of `frame`.
+    fn on_frame_end(&mut self, frame: &FrameReader<'a>) {}
+
+    fn on_layer(&mut self, layer: Layer<'a>) {}
+
+    fn on_cel(&mut self, cel: CelContainer<'a>) {}
+
+    /// Called for 0x2006 chunks. Cels read from a whole frame also carry it, see
+    /// [`CelContainer::precise_bounds`].
+    fn on_cel_extra(&mut self, extra: CelExtra) {}
+
+    fn on_color_profile(&mut self, profile: ColorProfile<'a>) {}
+
+    fn on_external_files(&mut self, files: ExternalFiles<'a>) {}
+
+    fn on_palette(&mut self, palette: Palette<'a>) {}
+
+    /// Called for 0x0004 and 0x0011 chunks, which readers should ignore when the file
+    /// also has 0x2019 palette chunks.
+    fn on_old_palette(&mut self, palette: OldPalette<'a>) {}
+
+    /// Called for the deprecated 0x2016 mask chunks of old files.
+    fn on_mask(&mut self, mask: Mask<'a>) {}
+
+    fn on_tags(&mut self, tags: Tags<'a>) {}
+
+    fn on_user_data(&mut self, user_data: UserData<'a>) {}
+
+    fn on_slice(&mut self, slice: Slice<'a>) {}
+
+    fn on_tileset(&mut self, tileset: Tileset<'a>) {}
+
+    /// Called for chunks that have neither a built-in nor a registered decoder.
+    fn on_unknown_chunk(&mut self, chunk: RawChunk<'a>) {}
+
+    /// Called when a frame or chunk cannot be read.
+    ///
+    /// Returning the error stops the walk, which is the default. Returning `Ok(())`
+    /// skips the chunk, or ends the frame when the chunk header itself is broken.
+    fn on_error(&mut self, error: Error) -> Result<(), Error> {
+        Err(error)
+    }
+}
+
+/// Decoder for one chunk type, registered with [`Walker::with_decoders`].
+///
+/// Error offsets are relative to the start of the chunk payload, the walker adds the
+/// location of the chunk before handing the error to [`AseVisitor::on_error`].
+pub type ChunkDecoder<'a, V> = fn(&mut V, RawChunk<'a>) -> Result<(), Error>;
+
+/// Drives an [`AseVisitor`] over a file, with optional custom chunk decoders.
+pub struct Walker<'r, 'a, V> {
+    decoders: &'r [(u16, ChunkDecoder<'a, V>)],
+}
+
+impl<'r, 'a, V: AseVisitor<'a>> Walker<'r, 'a, V> {
+    /// A walker that only uses the built-in chunk decoders.
+    pub const fn new() -> Self {
+        Walker { decoders: &[] }
+    }
+
+    /// A walker that hands chunks of the given types to their decoder instead of the
+    /// built-in ones. When a type is listed twice the first entry wins.
+    pub const fn with_decoders(decoders: &'r [(u16, ChunkDecoder<'a, V>)]) -> Self {
+        Walker { decoders }
+    }
+
+    /// Validates the header of `data` and walks the whole file.
+    pub fn walk(&self, data: &'a [u8], visitor: &mut V) -> Result<(), Error> {
+        self.walk_reader(&HeaderReader::try_new(data)?, visitor)
+    }
+
+    /// Walks a file that was already opened.
+    pub fn walk_reader(&self, reader: &HeaderReader<'a>, visitor: &mut V) -> Result<(), Error> {
+        visitor.on_header(reader);
+        for frame in reader.frames() {
+            let frame = match frame {
+                Ok(frame) => frame,
+                Err(e) => {
+                    visitor.on_error(e)?;
+                    continue;
+                }
+            };
+            visitor.on_frame_start(&frame);
+            let mut chunks = frame.chunks();
+            while let Some(chunk) = chunks.next_raw() {
+                if let Err(e) = chunk.and_then(|chunk| self.visit_chunk(chunk, visitor)) {
+                    visitor.on_error(e)?;
+                }
+            }
+            visitor.on_frame_end(&frame);
+        }
+        Ok(())
+    }
+
+    fn visit_chunk(&self, chunk: RawChunk<'a>, visitor: &mut V) -> Result<(), Error> {
+        if let Some((_, decoder)) = self.decoders.iter().find(|(t, _)| *t == chunk.chunk_type()) {
+            return decoder(visitor, chunk).map_err(|e| chunk.locate(e));
+        }
+        match chunk.decode()? {
+            ASEChunk::Layer(layer) => visitor.on_layer(layer),
+            ASEChunk::Cel(cel) => visitor.on_cel(cel),
+            ASEChunk::Palette(palette) => visitor.on_palette(palette),
+            ASEChunk::OldPalette(palette) => visitor.on_old_palette(palette),
+            ASEChunk::Tags(tags) => visitor.on_tags(tags),
+            ASEChunk::UserData(user_data) => visitor.on_user_data(user_data),
+            ASEChunk::CelExtra(extra) => visitor.on_cel_extra(extra),
+            ASEChunk::ColorProfile(profile) => visitor.on_color_profile(profile),
+            ASEChunk::ExternalFiles(files) => visitor.on_external_files(files),
+            ASEChunk::Mask(mask) => visitor.on_mask(mask),
+            ASEChunk::Slice(slice) => visitor.on_slice(slice),
+            ASEChunk::Tileset(tileset) => visitor.on_tileset(tileset),
+            ASEChunk::Unknown(..) => visitor.on_unknown_chunk(chunk),
+        }
+        Ok(())
+    }
+}
+
+impl<'a, V: AseVisitor<'a>> Default for Walker<'_, 'a, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Walks `data` with the built-in chunk decoders, see [`Walker::walk`].
+pub fn walk<'a, V: AseVisitor<'a>>(data: &'a [u8], visitor: &mut V) -> Result<(), Error> {
+    Walker::new().walk(data, visitor)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::error::ErrorKind;
+    use crate::test_util::{FileBuilder, chunk, string};
+    use std::{vec, vec::Vec};
+
+    #[derive(Default)]
+    struct Counter<'a> {
+        frames: Vec<(u16, u16)>,
+        layers: Vec<&'a str>,
+        cels: usize,
+        color_profiles: usize,
+        unknown: Vec<u16>,
+        errors: Vec<Error>,
+        skip_errors: bool,
+        profile: Option<u16>,
+    }
+
+    impl<'a> AseVisitor<'a> for Counter<'a> {
+        fn on_frame_start(&mut self, frame: &FrameReader<'a>) {
+            self.frames.push((frame.index(), frame.duration()));
+        }
+
+        fn on_layer(&mut self, layer: Layer<'a>) {
+            self.layers.push(layer.name);
+        }
+
+        fn on_cel(&mut self, _cel: CelContainer<'a>) {
+            self.cels += 1;
+        }
+
+        fn on_color_profile(&mut self, _profile: ColorProfile<'a>) {
+            self.color_profiles += 1;
+        }
+
+        fn on_unknown_chunk(&mut self, chunk: RawChunk<'a>) {
+            self.unknown.push(chunk.chunk_type());
+        }
+
+        fn on_error(&mut self, error: Error) -> Result<(), Error> {
+            self.errors.push(error);
+            if self.skip_errors { Ok(()) } else { Err(error) }
+        }
+    }
+
+    fn color_profile<'a>(visitor: &mut Counter<'a>, chunk: RawChunk<'a>) -> Result<(), Error> {
+        let &[a, b, ..] = chunk.data() else {
+            return Err(Error::truncated(0, 2, chunk.data().len()));
+        };
+        visitor.profile = Some(u16::from_le_bytes([a, b]));
+        Ok(())
+    }
+
+    fn failing<'a>(_: &mut Counter<'a>, _: RawChunk<'a>) -> Result<(), Error> {
+        Err(Error::bad_enum(2, "private field", 7))
+    }
+
+    #[test]
+    fn walk_visits_every_chunk() {
+        let v = std::fs::read("tests/anim_idle.ase").unwrap();
+        let mut counter = Counter::default();
+        walk(&v, &mut counter).unwrap();
+
+        assert_eq!(counter.frames.len(), 12);
+        assert_eq!(counter.frames[2], (2, 200));
+        assert_eq!(counter.layers.len(), 7);
+
+        let r = HeaderReader::new(&v);
+        let cels = r.frames().flat_map(|f| f.unwrap().chunks()).filter(|c| matches!(c, Ok(ASEChunk::Cel(_)))).count();
+        assert_eq!(counter.cels, cels);
+        assert_eq!(counter.color_profiles, 1);
+    }
+
+    #[test]
+    fn registered_decoders_replace_builtin_ones() {
+        let v = std::fs::read("tests/anim_idle.ase").unwrap();
+        let mut counter = Counter::default();
+        let decoders: [(u16, ChunkDecoder<'_, Counter<'_>>); 1] = [(0x2007, color_profile)];
+        Walker::with_decoders(&decoders).walk(&v, &mut counter).unwrap();
+
+        assert_eq!(counter.profile, Some(1));
+        assert!(!counter.unknown.contains(&0x2007));
+    }
+
+    #[test]
+    fn errors_go_to_the_visitor() {
+        let layer = chunk(0x2004, &[&[0u8; 16][..], &string("a")].concat());
+        let data = FileBuilder::new()
+            .frame(0, &[chunk(0x4242, &[0; 4]), layer])
+            .build();
+        let decoders: [(u16, ChunkDecoder<'_, Counter<'_>>); 1] = [(0x4242, failing)];
+
+        let mut counter = Counter::default();
+        let e = Walker::with_decoders(&decoders).walk(&data, &mut counter).unwrap_err();
+        assert_eq!(e.kind, ErrorKind::BadEnumValue { field: "private field", value: 7 });
+        assert_eq!(e.offset, 128 + 16 + 6 + 2);
+        assert_eq!((e.frame, e.chunk, e.chunk_type), (Some(0), Some(0), Some(0x4242)));
+        assert!(counter.layers.is_empty());
+
+        let mut counter = Counter { skip_errors: true, ..Default::default() };
+        Walker::with_decoders(&decoders).walk(&data, &mut counter).unwrap();
+        assert_eq!(counter.errors.len(), 1);
+        assert_eq!(counter.layers, vec!["a"]);
+    }
+}
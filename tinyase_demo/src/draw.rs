@@ -2,17 +2,69 @@ use core::{fmt, time::Duration};
 use std::vec;
 
 use pixels::Pixels;
-use tinyase::parser::{HeaderReader, chunk::{self, CelData}, frame};
+use tinyase::parser::{HeaderReader, Rgba, chunk::{self, CelData, tags::{LoopDirection, Tag}}, frame};
 use tiny_skia::{self, IntSize, PixmapMut, PixmapPaint, Transform};
 
 
 
 pub struct ASEDrawing<'a> {
-    pub reader: HeaderReader<'a>
+    pub reader: HeaderReader<'a>,
+    palette: [Rgba; 256],
+    /// Frame indices of one loop of the animation
+    sequence: Vec<u16>,
 }
 
 
 impl<'a> ASEDrawing<'a> {
+    /// Plays the frames of `tag`, or every frame when there is none.
+    pub fn new(reader: HeaderReader<'a>, tag: Option<Tag<'_>>) -> Self {
+        let mut palette = [Rgba::TRANSPARENT; 256];
+        reader.palette_for_frame(0, &mut palette).expect("Invalid palette");
+        // Sprites drawn in linear or fixed gamma space look washed out unless converted
+        if let Some(converter) = reader.color_profile().ok().flatten().and_then(|p| p.srgb_converter()) {
+            palette.iter_mut().for_each(|color| *color = converter.convert(*color));
+        }
+        let last = reader.header().frames().saturating_sub(1);
+        let (from, to, direction) = match tag {
+            Some(tag) => (tag.from.min(last), tag.to.min(last), tag.direction),
+            None => (0, last, LoopDirection::Forward),
+        };
+        let forward: Vec<u16> = (from..=to).collect();
+        let reverse: Vec<u16> = forward.iter().rev().copied().collect();
+        let inner = |frames: &[u16]| frames.get(1..frames.len().saturating_sub(1)).unwrap_or_default().to_vec();
+        let sequence = match direction {
+            LoopDirection::Forward => forward,
+            LoopDirection::Reverse => reverse,
+            LoopDirection::PingPong => [forward, inner(&reverse)].concat(),
+            LoopDirection::PingPongReverse => [reverse, inner(&forward)].concat(),
+        };
+        ASEDrawing { reader, palette, sequence }
+    }
+
+    fn find_cel(&self, frame: u16, layer: u16) -> Option<chunk::CelContainer<'a>> {
+        self.reader.frames().nth(frame as usize)?.ok()?.chunks().find_map(|chunk| match chunk {
+            Ok(chunk::ASEChunk::Cel(c)) if c.cel_header.layer_index() == layer => Some(c),
+            _ => None,
+        })
+    }
+
+    /// Frame to show `elapsed` after the start of the animation
+    fn current_frame(&self, elapsed: Duration) -> u16 {
+        let duration = |index: u16| {
+            self.reader.frames().nth(index as usize).and_then(Result::ok).map_or(100, |f| f.duration() as u128)
+        };
+        let total: u128 = self.sequence.iter().map(|&i| duration(i)).sum();
+        let mut t = elapsed.as_millis() % total.max(1);
+        for &index in &self.sequence {
+            let d = duration(index);
+            if t < d {
+                return index;
+            }
+            t -= d;
+        }
+        self.sequence[0]
+    }
+
     pub fn draw(&self, pixel: &mut Pixels, elapsed: Duration) {
         let buf_size = (pixel.texture().size().width, pixel.texture().size().height);
 
@@ -20,35 +72,63 @@ impl<'a> ASEDrawing<'a> {
 
         frame_pixmap.fill(tiny_skia::Color::from_rgba8(0x8b, 0x8b, 0x8b, 0xff));
 
-        // --- ANIMATION LOGIC ---
-        // Get elapsed time in seconds as a float
-        let elapsed = elapsed.as_secs_f32();
+        // Non-square pixels are drawn as blocks of the pixel ratio
+        let (ratio_width, ratio_height) = self.pixel_ratio();
+        let scale = Transform::from_scale(ratio_width as f32, ratio_height as f32);
 
-        let mut frames = self.reader.frames();
-        let frame_1 = frames.next().unwrap();
-        let mut chunks = frame_1.chunks();
+        // --- ANIMATION LOGIC ---
+        let current = self.current_frame(elapsed);
+        let frame = self.reader.frames().nth(current as usize).unwrap().unwrap();
+        let mut chunks = frame.chunks();
         for chunk in chunks {
             match chunk {
-                chunk::ASEChunk::Cel(c) => {
-                    let cd = c.get();
-                    match cd {
-                        CelData::Raw(raw) => {
+                Ok(chunk::ASEChunk::Cel(c)) => {
+                    // Linked cels reuse the cel of the same layer in another frame
+                    let linked = match c.get() {
+                        Ok(CelData::Linked(source)) => Some(source),
+                        _ => None,
+                    };
+                    let c = match linked {
+                        Some(source) => match self.find_cel(source, c.cel_header.layer_index()) {
+                            Some(c) => c,
+                            None => continue,
+                        },
+                        None => c,
+                    };
+                    // Aseprite saves compressed cels, they decode to the same layout as raw ones
+                    let mut decompressed = vec![];
+                    let raw = match c.get() {
+                        Ok(CelData::Raw(raw)) => Some(raw),
+                        Ok(CelData::Compressed(image)) => {
+                            let depth = self.reader.color_depth();
+                            image.required_len(depth).ok().and_then(|len| {
+                                decompressed.resize(len, 0);
+                                image.decompress(depth, &mut decompressed).ok()
+                            })
+                        }
+                        _ => None,
+                    };
+                    match raw {
+                        Some(raw) => {
                             let ch = c.cel_header;
                             
-                            let width = raw.header.width as usize;
-                            let height = raw.header.height as usize;
+                            let width = raw.header.width() as usize;
+                            let height = raw.header.height() as usize;
                             let src_ptr = raw.ptr;
                             // for each indexed color of raw.ptr we convert to rgba
                             // create target pixmap array of width and height, 4bpp
                             let mut img_buf = vec![0u8; width * height * 4];
 
+                            let transparent_index = self.reader.header().transparent_index();
                             for (i, target) in img_buf.chunks_exact_mut(4).enumerate() {
-                                match src_ptr[i] {
-                                    0 => target.copy_from_slice(&[0,0,0,0]),
-                                    1 => target.copy_from_slice(&[22,18,54,255]),
-                                    2 => target.copy_from_slice(&[255,255,255,255]),
-                                    _ => target.copy_from_slice(&[255,255,255,255]),
+                                let index = src_ptr.get(i).copied().unwrap_or(transparent_index);
+                                let color = match index == transparent_index {
+                                    true => Rgba::TRANSPARENT,
+                                    false => self.palette[index as usize],
                                 };
+                                // tiny-skia wants premultiplied alpha
+                                let premultiply = |c: u8| (c as u16 * color.a as u16 / 255) as u8;
+                                target.copy_from_slice(&[premultiply(color.r), premultiply(color.g), premultiply(color.b), color.a]);
                             }
                             // println!("cel: {:?}", raw.header);
 
@@ -56,7 +136,7 @@ impl<'a> ASEDrawing<'a> {
                             let img_pixmap = tiny_skia::Pixmap::from_vec(img_buf, IntSize::from_wh(width as _, height as _).unwrap()).unwrap();
                             // let img_pixmap = tiny_skia::PixmapMut::from_bytes(&mut img_buf, width as _ , height as _ ).unwrap();
 
-                            frame_pixmap.draw_pixmap(ch.point_x as _, ch.point_y as _, img_pixmap.as_ref(), &PixmapPaint::default(), Transform::default(), None);
+                            frame_pixmap.draw_pixmap(ch.point_x() as _, ch.point_y() as _, img_pixmap.as_ref(), &PixmapPaint::default(), scale, None);
 
                             
 
@@ -89,6 +169,11 @@ impl<'a> ASEDrawing<'a> {
 
     pub fn size(&self) -> (u32, u32) {
         let header = self.reader.header();
-        (header.width as u32, header.height as u32)
+        (header.width() as u32, header.height() as u32)
+    }
+
+    pub fn pixel_ratio(&self) -> (u32, u32) {
+        let ratio = self.reader.header().pixel_ratio();
+        (ratio.width as u32, ratio.height as u32)
     }
 }
\ No newline at end of file
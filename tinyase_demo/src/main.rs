@@ -23,15 +23,16 @@ struct App<'a> {
 impl<'a> ApplicationHandler for App<'a> {
     fn resumed(&mut self, event_loop: &winit::event_loop::ActiveEventLoop) {
         let (width, height) = self.drawing.size();
+        let (ratio_width, ratio_height) = self.drawing.pixel_ratio();
         let scaling = 8;
-        let (window_width, window_height) = (width * scaling, height * scaling);
+        let (window_width, window_height) = (width * scaling * ratio_width, height * scaling * ratio_height);
         let window_attributes = Window::default_attributes()
             .with_title("Animated Raw Pixels")
             .with_inner_size(winit::dpi::LogicalSize::new(window_width, window_height));
         
         let window = Arc::new(event_loop.create_window(window_attributes).unwrap());
         let surface_texture = SurfaceTexture::new(window_width, window_height, Arc::clone(&window));
-        let pixels = Pixels::new(width, height, surface_texture).unwrap();
+        let pixels = Pixels::new(width * ratio_width, height * ratio_height, surface_texture).unwrap();
         self.window = Some(window);
         self.pixels = Some(pixels);
     }
@@ -68,6 +69,9 @@ fn main() {
     struct Args {
         /// .ase or .aseprite file to open. See tinyase/tests/anim_idle.ase for an example file.
         aseprite_file: String,
+        /// Name of the animation tag to play, every frame is played when omitted.
+        #[arg(long)]
+        tag: Option<String>,
     }
 
     let args = Args::parse();
@@ -78,10 +82,11 @@ fn main() {
     event_loop.set_control_flow(ControlFlow::Poll);
 
     let file_buf = std::fs::read(&args.aseprite_file).expect("Failed to find aseprite file");
-    let header_reader = tinyase::parser::HeaderReader::new(&file_buf);
-    let drawing = ASEDrawing {
-        reader: header_reader,
-    };
+    let header_reader = tinyase::parser::HeaderReader::try_new(&file_buf).expect("Invalid aseprite file");
+    let tag = args.tag.as_deref().map(|name| {
+        header_reader.tag(name).expect("Invalid tags chunk").unwrap_or_else(|| panic!("No tag named {name}"))
+    });
+    let drawing = ASEDrawing::new(header_reader, tag);
     
     let mut app = App {
         window: None,